@@ -0,0 +1,38 @@
+//! Feeds arbitrary bytes to `MessageReader`, run with `cargo fuzz run message_reader`
+#![no_main]
+#![allow(dead_code)]
+
+#[path = "../../src/message.rs"]
+mod message;
+#[path = "../../src/test.rs"]
+mod test;
+#[path = "../../src/transport.rs"]
+mod transport;
+
+use libfuzzer_sys::fuzz_target;
+use message::MessageReader;
+use std::cell::RefCell;
+use std::io::{Cursor, Read};
+use std::rc::Rc;
+use transport::SetReadTimeout;
+
+#[derive(Clone)]
+struct Input(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+impl Read for Input {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().read(buffer)
+    }
+}
+
+impl SetReadTimeout for Input {
+    fn set_read_timeout(&mut self, _: Option<u64>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = MessageReader::new(Input(Rc::new(RefCell::new(Cursor::new(data.to_vec())))));
+    // Every error but running out of input leaves the reader able to go on
+    while !matches!(reader.read(), Err(message::Error::IO { .. })) {}
+});
@@ -0,0 +1,125 @@
+use serde::Deserialize;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+/// An IPv4 network such as "10.0.0.0/8", a single address if the prefix length is omitted
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    network: Ipv4Addr,
+    prefix_length: u8,
+}
+
+impl Cidr {
+    fn mask(&self) -> u32 {
+        u32::MAX
+            .checked_shl(32 - self.prefix_length as u32)
+            .unwrap_or(0)
+    }
+
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        u32::from(address) & self.mask() == u32::from(self.network) & self.mask()
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (network, prefix_length) = match text.split_once('/') {
+            Some((network, prefix_length)) => (
+                network,
+                prefix_length
+                    .parse()
+                    .ok()
+                    .filter(|&length| length <= 32)
+                    .ok_or_else(|| format!("invalid prefix length in \"{}\"", text))?,
+            ),
+            None => (text, 32),
+        };
+        let network = network
+            .parse()
+            .map_err(|_| format!("invalid address in \"{}\"", text))?;
+        Ok(Self {
+            network,
+            prefix_length,
+        })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+/// Which clients a server accepts. A client is denied if any `deny` network contains it, or if
+/// `allow` is not empty and no `allow` network contains it
+#[derive(Deserialize, Debug, Default)]
+pub struct AccessRules {
+    #[serde(default)]
+    pub allow: Vec<Cidr>,
+    #[serde(default)]
+    pub deny: Vec<Cidr>,
+}
+
+impl AccessRules {
+    pub fn permits(&self, address: Ipv4Addr) -> bool {
+        !self.deny.iter().any(|cidr| cidr.contains(address))
+            && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(address)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(text: &str) -> Cidr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn prefix_zero_contains_everything() {
+        let all = cidr("0.0.0.0/0");
+        assert!(all.contains(Ipv4Addr::new(0, 0, 0, 0)));
+        assert!(all.contains(Ipv4Addr::new(192, 168, 1, 1)));
+        assert!(all.contains(Ipv4Addr::BROADCAST));
+    }
+
+    #[test]
+    fn prefix_32_contains_one_address() {
+        for single in [cidr("10.1.2.3/32"), cidr("10.1.2.3")] {
+            assert!(single.contains(Ipv4Addr::new(10, 1, 2, 3)));
+            assert!(!single.contains(Ipv4Addr::new(10, 1, 2, 4)));
+            assert!(!single.contains(Ipv4Addr::new(10, 1, 2, 2)));
+        }
+    }
+
+    #[test]
+    fn prefix_masks_host_bits() {
+        let network = cidr("10.1.2.3/8");
+        assert!(network.contains(Ipv4Addr::new(10, 255, 0, 1)));
+        assert!(!network.contains(Ipv4Addr::new(11, 0, 0, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_networks() {
+        for text in ["10.0.0.0/33", "10.0.0.0/", "10.0.0/8", "10.0.0.0/-1"] {
+            assert!(text.parse::<Cidr>().is_err(), "{} accepted", text);
+        }
+    }
+
+    #[test]
+    fn deny_takes_precedence() {
+        let rules = AccessRules {
+            allow: vec![cidr("10.0.0.0/8")],
+            deny: vec![cidr("10.0.0.1")],
+        };
+        assert!(rules.permits(Ipv4Addr::new(10, 0, 0, 2)));
+        assert!(!rules.permits(Ipv4Addr::new(10, 0, 0, 1)));
+        assert!(!rules.permits(Ipv4Addr::new(192, 168, 0, 1)));
+        assert!(AccessRules::default().permits(Ipv4Addr::new(192, 168, 0, 1)));
+    }
+}
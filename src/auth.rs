@@ -0,0 +1,62 @@
+use crate::c;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Bytes of the nonce the server challenges clients with
+const NONCE_SIZE: usize = 32;
+
+/// A pre-shared key, given inline or read from a file
+#[derive(Deserialize, Clone)]
+pub struct Credentials {
+    /// Tells the server which key to check, anonymous if not given
+    pub user: Option<String>,
+    pub key: Option<String>,
+    /// Used when `key` is not given, trailing whitespace is ignored
+    pub key_file: Option<String>,
+}
+
+impl Credentials {
+    pub fn key(&self) -> std::io::Result<Vec<u8>> {
+        match (&self.key, &self.key_file) {
+            (Some(key), _) => Ok(key.as_bytes().to_vec()),
+            (None, Some(path)) => {
+                let key = std::fs::read(path)?;
+                let length = key
+                    .iter()
+                    .rposition(|byte| !byte.is_ascii_whitespace())
+                    .map_or(0, |position| position + 1);
+                Ok(key[..length].to_vec())
+            }
+            (None, None) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "credentials need a key or a key_file",
+            )),
+        }
+    }
+}
+
+pub fn nonce() -> std::io::Result<Vec<u8>> {
+    let mut nonce = vec![0; NONCE_SIZE];
+    c::random_bytes(&mut nonce)?;
+    Ok(nonce)
+}
+
+fn mac(key: &[u8], nonce: &[u8], transcript: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(nonce);
+    mac.update(transcript);
+    mac
+}
+
+/// HMAC-SHA256 of the nonce followed by the transcript of the handshake
+pub fn sign(key: &[u8], nonce: &[u8], transcript: &[u8]) -> Vec<u8> {
+    mac(key, nonce, transcript).finalize().into_bytes().to_vec()
+}
+
+/// Compare in constant time
+pub fn verify(key: &[u8], nonce: &[u8], transcript: &[u8], signature: &[u8]) -> bool {
+    mac(key, nonce, transcript).verify_slice(signature).is_ok()
+}
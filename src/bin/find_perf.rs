@@ -1,9 +1,69 @@
-use sysinfo::{ProcessExt, System, SystemExt};
+use perf::control::{socket_path, Status};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use sysinfo::{PidExt, ProcessExt, System, SystemExt};
+
+fn query_status(pid: u32) -> std::io::Result<Status> {
+    let mut stream = UnixStream::connect(socket_path(pid))?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    stream.write_all(b"status\n")?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, line.trim().to_string()))
+}
+
+fn format_bits(bits_per_second: f64) -> String {
+    let units = ["bit/s", "Kbit/s", "Mbit/s", "Gbit/s", "Tbit/s"];
+    let mut value = bits_per_second;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < units.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.2}{}", value, units[unit])
+}
 
 fn main() {
     let mut system = System::new();
     system.refresh_all();
-    for p in system.processes_by_exact_name("perf") {
-        println!("{}", p.pid())
+    let mut pids: Vec<u32> = system
+        .processes_by_exact_name("perf")
+        .map(|p| p.pid().as_u32())
+        .collect();
+    pids.sort();
+
+    println!(
+        "{:<8} {:<6} {:<22} {:<10} {:<8} {:>9} {:>14}",
+        "PID", "TEST", "CLIENT", "TRANSPORT", "MODE", "ELAPSED", "BITRATE"
+    );
+    for pid in pids {
+        match query_status(pid) {
+            Ok(status) => {
+                let state = if status.shutting_down {
+                    "shutting down"
+                } else if status.active.is_empty() {
+                    "idle"
+                } else {
+                    "running"
+                };
+                println!("{:<8} {}, {} completed", pid, state, status.completed);
+                for test in status.active {
+                    println!(
+                        "{:<8} {:<6} {:<22} {:<10} {:<8} {:>8.1}s {:>14}",
+                        pid,
+                        format!("#{}", test.id),
+                        test.peer.map_or("-".to_string(), |peer| peer.to_string()),
+                        test.transport,
+                        test.mode.to_string(),
+                        test.elapsed,
+                        format_bits(test.bits_per_second),
+                    );
+                }
+            }
+            // Clients and older servers have no control socket
+            Err(e) => println!("{:<8} no status: {}", pid, e),
+        }
     }
 }
@@ -70,6 +70,15 @@ impl Fd {
     }
 
     pub fn set_timeout(&self, milliseconds: Option<u64>) -> std::io::Result<()> {
+        self.set_time_option(SO_RCVTIMEO, milliseconds)
+    }
+
+    /// Make blocked sends fail with `WouldBlock` after this long
+    pub fn set_write_timeout(&self, milliseconds: Option<u64>) -> std::io::Result<()> {
+        self.set_time_option(SO_SNDTIMEO, milliseconds)
+    }
+
+    fn set_time_option(&self, option: c_int, milliseconds: Option<u64>) -> std::io::Result<()> {
         unsafe {
             if let Some(seconds) = milliseconds {
                 let time = timeval {
@@ -78,17 +87,31 @@ impl Fd {
                 };
                 let len = std::mem::size_of_val(&time) as socklen_t;
                 let time = &time as *const timeval as *const c_void;
-                handle_os_result(setsockopt(self.value(), SOL_SOCKET, SO_RCVTIMEO, time, len))?;
+                handle_os_result(setsockopt(self.value(), SOL_SOCKET, option, time, len))?;
             } else {
                 let time = std::mem::zeroed::<timeval>();
                 let len = std::mem::size_of_val(&time) as socklen_t;
                 let time = &time as *const timeval as *const c_void;
-                handle_os_result(setsockopt(self.value(), SOL_SOCKET, SO_RCVTIMEO, time, len))?;
+                handle_os_result(setsockopt(self.value(), SOL_SOCKET, option, time, len))?;
             }
         }
 
         Ok(())
     }
+
+    /// Address the socket is bound to
+    pub fn local_address(&self) -> std::io::Result<SocketAddrV4> {
+        unsafe {
+            let mut address = std::mem::zeroed::<sockaddr_in>();
+            let mut address_length = std::mem::size_of::<sockaddr_in>() as socklen_t;
+            handle_os_result(getsockname(
+                self.value(),
+                &mut address as *mut sockaddr_in as *mut sockaddr,
+                &mut address_length as *mut socklen_t,
+            ))?;
+            Ok(SocketAddrV4::from_c(&address))
+        }
+    }
 }
 
 impl Drop for Fd {
@@ -96,3 +119,36 @@ impl Drop for Fd {
         unsafe { close(self.value()) };
     }
 }
+
+/// Host name of this machine
+pub fn hostname() -> std::io::Result<String> {
+    let mut buffer = [0u8; 256];
+    unsafe {
+        handle_os_result(gethostname(
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer.len(),
+        ))?;
+    }
+    let length = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    Ok(String::from_utf8_lossy(&buffer[..length]).into_owned())
+}
+
+/// Fill `buffer` from the kernel random number generator
+pub fn random_bytes(buffer: &mut [u8]) -> std::io::Result<()> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let read = unsafe {
+            getrandom(
+                buffer[filled..].as_mut_ptr() as *mut c_void,
+                buffer.len() - filled,
+                0,
+            )
+        };
+        match handle_os_result(read) {
+            Ok(read) => filled += read as usize,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
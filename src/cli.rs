@@ -1,10 +1,14 @@
 use clap::*;
+use perf::test::MIN_REPORT_INTERVAL;
 use std::fmt::{Display, Formatter};
 
 #[derive(Parser, ValueEnum, Clone, Debug)]
 pub enum FormatType {
     Json,
     Pretty,
+    Csv,
+    Influx,
+    Statsd,
 }
 
 impl Display for FormatType {
@@ -12,17 +16,106 @@ impl Display for FormatType {
         match self {
             FormatType::Json => f.write_str("json"),
             FormatType::Pretty => f.write_str("pretty"),
+            FormatType::Csv => f.write_str("csv"),
+            FormatType::Influx => f.write_str("influx"),
+            FormatType::Statsd => f.write_str("statsd"),
         }
     }
 }
 
 #[derive(Parser, Debug)]
+#[command(
+    after_help = "Exit codes: 1 failure, 2 invalid arguments, 3 invalid config, \
+4 connection failure, 5 protocol error, 6 error during the test, 7 regression, \
+128 + signal when interrupted"
+)]
 pub struct Command {
+    #[command(subcommand)]
+    pub action: Option<Action>,
+
     #[arg(short, long)]
     pub config: Option<String>,
 
     #[arg(short = 'f', long = "format", default_value_t = FormatType::Pretty)]
     pub format: FormatType,
+
+    /// Send formatted events to udp://host:port or tcp://host:port instead of stdout
+    #[arg(long)]
+    pub collector: Option<String>,
+
+    /// Seconds between reports, down to 0.01, or 0 for only the summary.
+    /// The report_interval of a test plan takes precedence
+    #[arg(short, long, default_value_t = 1.0, value_parser = parse_interval)]
+    pub interval: f64,
+
+    /// Write events as format:path, where path is `-` for stdout, a file or a collector URL.
+    /// Can be repeated, and cannot be combined with --format and --collector
+    #[arg(short, long, value_parser = parse_output, conflicts_with_all = ["format", "collector"])]
+    pub output: Vec<OutputSpec>,
+
+    /// Write an HTML report with charts of the tests, updated after each test
+    #[arg(long)]
+    pub html: Option<String>,
+
+    /// Run in the background, for long-running servers. Needs --config as stdin is closed
+    #[arg(long, requires = "config")]
+    pub daemon: bool,
+
+    /// Write the process ID of the daemon to this file, removed on exit
+    #[arg(long, requires = "daemon")]
+    pub pid_file: Option<String>,
+
+    /// Append stdout and stderr of the daemon to this file instead of discarding them.
+    /// SIGHUP reopens it, e.g. after log rotation
+    #[arg(long, requires = "daemon")]
+    pub log_file: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Action {
+    /// Generate an HTML report from a JSON event log written with --format json
+    Report {
+        /// JSON event log, `-` for stdin
+        events: String,
+        /// Where to write the HTML report
+        html: String,
+    },
+    /// Run the tests, or read a JSON event log, and compare every test with a baseline.
+    /// Exits with 7 on regression
+    Compare {
+        /// TOML file with a table per metric, e.g. `[bits_per_second]` with `value = 9.4e9` and
+        /// `tolerance = 0.1` (0.05 by default), or with `min` and `max`. Rates may not drop
+        /// below the tolerance, elapsed and stddev may not grow above it
+        baseline: String,
+        /// JSON event log written with --format json, instead of running the tests
+        #[arg(long)]
+        events: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct OutputSpec {
+    pub format: FormatType,
+    pub path: String,
+}
+
+fn parse_interval(value: &str) -> Result<f64, String> {
+    let interval: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if interval == 0.0 || interval >= MIN_REPORT_INTERVAL {
+        Ok(interval)
+    } else {
+        Err(format!("must be 0 or at least {}", MIN_REPORT_INTERVAL))
+    }
+}
+
+fn parse_output(value: &str) -> Result<OutputSpec, String> {
+    let (format, path) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected format:path, got \"{}\"", value))?;
+    Ok(OutputSpec {
+        format: FormatType::from_str(format, true)?,
+        path: path.to_string(),
+    })
 }
 
 pub fn parse() -> Command {
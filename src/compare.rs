@@ -0,0 +1,297 @@
+use crate::exit_code;
+use crate::report::TestRecord;
+use crate::test::Statistics;
+use crate::test_format::format_number;
+use serde::Deserialize;
+use snafu::{prelude::*, Backtrace};
+use std::collections::BTreeMap;
+
+#[derive(Snafu, Debug)]
+pub enum Error {
+    #[snafu(display("invalid baseline: {}", source), context(false))]
+    Baseline {
+        #[snafu(source(from(toml::de::Error, Box::new)))]
+        source: Box<toml::de::Error>,
+        backtrace: Backtrace,
+    },
+    #[snafu(display("unknown metric \"{}\" in baseline", name))]
+    UnknownMetric { name: String, backtrace: Backtrace },
+    #[snafu(display("no test to compare with the baseline"))]
+    NoTests { backtrace: Backtrace },
+    #[snafu(display("{} regressions against the baseline", count))]
+    Regression { count: usize, backtrace: Backtrace },
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Exit code of the process when a comparison ends with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Baseline { .. } | Error::UnknownMetric { .. } => exit_code::CONFIG,
+            Error::NoTests { .. } => exit_code::FAILURE,
+            Error::Regression { .. } => exit_code::THRESHOLD,
+        }
+    }
+}
+
+/// Allowed range of a metric, every given bound must hold
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    /// Expected value
+    value: Option<f64>,
+    /// Relative deviation allowed from `value` in the direction of a regression, e.g. 0.05 for
+    /// 5%. Any improvement passes
+    #[serde(default = "default_tolerance")]
+    tolerance: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+fn default_tolerance() -> f64 {
+    0.05
+}
+
+/// Which way a metric regresses
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Direction {
+    /// Such as throughput, which regresses when it drops
+    HigherIsBetter,
+    /// Such as the elapsed time or the jitter of the rates, which regress when they grow
+    LowerIsBetter,
+}
+
+fn direction(name: &str) -> Direction {
+    if name == "elapsed" || name.ends_with("_stddev") {
+        Direction::LowerIsBetter
+    } else {
+        Direction::HigherIsBetter
+    }
+}
+
+impl Threshold {
+    /// The bound `value` and `tolerance` put on a metric
+    fn bound(&self, direction: Direction) -> Option<f64> {
+        let value = self.value?;
+        let deviation = value.abs() * self.tolerance;
+        Some(match direction {
+            Direction::HigherIsBetter => value - deviation,
+            Direction::LowerIsBetter => value + deviation,
+        })
+    }
+
+    fn holds(&self, actual: f64, direction: Direction) -> bool {
+        let within_tolerance = self.bound(direction).is_none_or(|bound| match direction {
+            Direction::HigherIsBetter => actual >= bound,
+            Direction::LowerIsBetter => actual <= bound,
+        });
+        within_tolerance
+            && self.min.is_none_or(|min| actual >= min)
+            && self.max.is_none_or(|max| actual <= max)
+    }
+
+    fn describe(&self, direction: Direction) -> String {
+        let mut bounds = Vec::new();
+        if let (Some(value), Some(bound)) = (self.value, self.bound(direction)) {
+            let (comparison, sign) = match direction {
+                Direction::HigherIsBetter => (">=", '-'),
+                Direction::LowerIsBetter => ("<=", '+'),
+            };
+            bounds.push(format!(
+                "{} {} ({} {}{}%)",
+                comparison,
+                format_number(bound),
+                format_number(value),
+                sign,
+                self.tolerance * 100.0
+            ));
+        }
+        if let Some(min) = self.min {
+            bounds.push(format!(">= {}", format_number(min)));
+        }
+        if let Some(max) = self.max {
+            bounds.push(format!("<= {}", format_number(max)));
+        }
+        bounds.join(", ")
+    }
+}
+
+/// Expected metrics of every test, by metric name
+#[derive(Deserialize, Debug)]
+pub struct Baseline(BTreeMap<String, Threshold>);
+
+impl Baseline {
+    pub fn parse(text: &str) -> Result<Self> {
+        let baseline: Baseline = toml::from_str(text)?;
+        for name in baseline.0.keys() {
+            if !METRICS.contains(&name.as_str()) {
+                return UnknownMetricSnafu { name }.fail();
+            }
+        }
+        Ok(baseline)
+    }
+}
+
+const METRICS: &[&str] = &[
+    "elapsed",
+    "bytes",
+    "packets",
+    "bits_per_second",
+    "bits_per_second_min",
+    "bits_per_second_max",
+    "bits_per_second_median",
+    "bits_per_second_stddev",
+    "packets_per_second",
+    "packets_per_second_min",
+    "packets_per_second_max",
+    "packets_per_second_median",
+    "packets_per_second_stddev",
+];
+
+fn statistic(statistics: &Statistics, suffix: &str) -> Option<f64> {
+    match suffix {
+        "" => Some(statistics.average),
+        "_min" => Some(statistics.min),
+        "_max" => Some(statistics.max),
+        "_median" => Some(statistics.median),
+        "_stddev" => Some(statistics.standard_deviation),
+        _ => None,
+    }
+}
+
+/// Value of a metric of a finished test
+fn metric(test: &TestRecord, name: &str) -> Option<f64> {
+    let summary = test.summary.as_ref()?;
+    match name {
+        "elapsed" => Some(summary.elapsed),
+        "bytes" => Some(test.data.total_transfer as f64),
+        "packets" => Some(test.data.total_packets as f64),
+        _ => {
+            if let Some(suffix) = name.strip_prefix("bits_per_second") {
+                statistic(&summary.bits_per_second, suffix)
+            } else if let Some(suffix) = name.strip_prefix("packets_per_second") {
+                statistic(&summary.packets_per_second, suffix)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Print how each test compares to the baseline and fail if any regressed
+pub fn compare(baseline: &Baseline, tests: &[TestRecord]) -> Result<()> {
+    ensure!(!tests.is_empty(), NoTestsSnafu);
+
+    let mut regressions: usize = 0;
+    for test in tests {
+        println!("Test #{}", test.data.id);
+        match &test.summary {
+            None => {
+                println!("  not finished");
+                regressions += 1;
+                continue;
+            }
+            Some(summary) => {
+                if let Some(error) = &summary.error {
+                    println!("  failed: {}", error);
+                    regressions += 1;
+                }
+            }
+        }
+
+        for (name, threshold) in &baseline.0 {
+            let Some(actual) = metric(test, name) else {
+                continue;
+            };
+            let direction = direction(name);
+            let status = if threshold.holds(actual, direction) {
+                "ok"
+            } else {
+                regressions += 1;
+                "REGRESSION"
+            };
+            let change = match threshold.value {
+                Some(value) if value != 0.0 => {
+                    format!(" ({:+.2}%)", (actual - value) / value * 100.0)
+                }
+                _ => String::new(),
+            };
+            println!(
+                "  {:<10} {:<26} expected {}, actual {}{}",
+                status,
+                name,
+                threshold.describe(direction),
+                format_number(actual),
+                change,
+            );
+        }
+    }
+
+    ensure!(regressions == 0, RegressionSnafu { count: regressions });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn threshold(text: &str) -> Threshold {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn default_tolerance_allows_small_drop() {
+        let rate = threshold("value = 1000.0");
+        let direction = direction("bits_per_second");
+        assert!(rate.holds(960.0, direction));
+        assert!(!rate.holds(940.0, direction));
+    }
+
+    #[test]
+    fn improvements_always_hold() {
+        let rate = threshold("value = 1000.0\ntolerance = 0.0");
+        assert!(rate.holds(1e6, direction("bits_per_second")));
+        let elapsed = threshold("value = 10.0\ntolerance = 0.0");
+        assert!(elapsed.holds(0.1, direction("elapsed")));
+        assert!(elapsed.holds(0.1, direction("packets_per_second_stddev")));
+    }
+
+    #[test]
+    fn lower_is_better_regresses_upwards() {
+        let elapsed = threshold("value = 10.0\ntolerance = 0.1");
+        assert!(elapsed.holds(10.9, direction("elapsed")));
+        assert!(!elapsed.holds(11.1, direction("elapsed")));
+        assert_eq!(
+            elapsed.describe(direction("elapsed")),
+            "<= 11.00 (10.00 +10%)"
+        );
+    }
+
+    #[test]
+    fn min_and_max_bound_both_directions() {
+        let packets = threshold("min = 10.0\nmax = 20.0");
+        let direction = direction("packets");
+        assert!(packets.holds(15.0, direction));
+        assert!(!packets.holds(5.0, direction));
+        assert!(!packets.holds(25.0, direction));
+    }
+
+    #[test]
+    fn baseline_rejects_unknown_metric() {
+        assert!(matches!(
+            Baseline::parse("[throughput]\nvalue = 1.0"),
+            Err(Error::UnknownMetric { .. })
+        ));
+        assert!(Baseline::parse("[bits_per_second_median]\nvalue = 1.0").is_ok());
+    }
+
+    #[test]
+    fn no_tests_is_an_error() {
+        let baseline = Baseline::parse("[bytes]\nmin = 1.0").unwrap();
+        assert!(matches!(
+            compare(&baseline, &[]),
+            Err(Error::NoTests { .. })
+        ));
+    }
+}
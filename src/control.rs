@@ -0,0 +1,208 @@
+use crate::test::TestHandle;
+use crate::transport::TransportMode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs::{DirBuilder, Permissions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddrV4;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// Path of the control socket, removed on exit
+static SOCKET_PATH: OnceLock<CString> = OnceLock::new();
+/// Control connections served at once, further ones are refused
+const MAX_CONNECTIONS: usize = 4;
+/// Seconds an idle control connection is kept open
+const IDLE_TIMEOUT: u64 = 60;
+
+/// Where the server with process ID `pid` listens for control commands
+pub fn socket_path(pid: u32) -> PathBuf {
+    std::env::temp_dir().join(format!("perf-{}.sock", pid))
+}
+
+/// A running test as reported by `status`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TestStatus {
+    pub id: usize,
+    /// Address of the client
+    pub peer: Option<SocketAddrV4>,
+    pub transport: String,
+    /// What the server does
+    pub mode: TransportMode,
+    /// Seconds, after the omitted warm-up
+    pub elapsed: f64,
+    pub bits_per_second: f64,
+}
+
+/// Answer to `status`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Status {
+    pub active: Vec<TestStatus>,
+    pub completed: usize,
+    pub shutting_down: bool,
+}
+
+#[derive(Default)]
+struct ServerState {
+    active: BTreeMap<usize, TestHandle>,
+    completed: usize,
+    shutting_down: bool,
+}
+
+/// Tests of a server, shared with its control socket
+#[derive(Clone, Default)]
+pub struct ServerStatus {
+    state: Arc<Mutex<ServerState>>,
+    /// Whether the running test ends early, kept out of the lock as it is checked per packet
+    stopped: Arc<AtomicBool>,
+}
+
+impl ServerStatus {
+    pub fn begin(&self, id: usize, test: TestHandle) {
+        self.state.lock().unwrap().active.insert(id, test);
+    }
+
+    pub fn end(&self, id: usize) {
+        let mut state = self.state.lock().unwrap();
+        if state.active.remove(&id).is_some() {
+            state.completed += 1;
+        }
+    }
+
+    /// Stop accepting clients, the server ends once its running tests are done
+    pub fn shut_down(&self) {
+        self.state.lock().unwrap().shutting_down = true;
+    }
+
+    /// Whether the server should stop once its running tests are done
+    pub fn is_shutting_down(&self) -> bool {
+        self.state.lock().unwrap().shutting_down
+    }
+
+    /// Stop accepting clients and end the running tests early
+    pub fn stop(&self) {
+        self.shut_down();
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    fn status(&self) -> Status {
+        let state = self.state.lock().unwrap();
+        let active = state
+            .active
+            .iter()
+            .map(|(&id, test)| {
+                let data = test.data();
+                TestStatus {
+                    id,
+                    peer: data.peer,
+                    transport: data.transport.clone(),
+                    mode: data.mode.clone(),
+                    elapsed: data.elapsed().as_secs_f64(),
+                    bits_per_second: test.bits_per_second(),
+                }
+            })
+            .collect();
+        Status {
+            active,
+            completed: state.completed,
+            shutting_down: state.shutting_down,
+        }
+    }
+
+    /// Run a command and return the answer
+    fn execute(&self, command: &str) -> String {
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("status"), None, _) => {
+                serde_json::to_string(&self.status()).unwrap_or_else(|e| format!("error: {}", e))
+            }
+            (Some("abort"), Some(id), None) => {
+                let state = self.state.lock().unwrap();
+                match id.parse().ok().and_then(|id: usize| state.active.get(&id)) {
+                    Some(test) => {
+                        test.abort();
+                        "ok".to_string()
+                    }
+                    None => format!("error: no running test {}", id),
+                }
+            }
+            (Some("shutdown"), None, _) => {
+                self.shut_down();
+                match self.state.lock().unwrap().active.len() {
+                    0 => "ok".to_string(),
+                    running => format!("ok, stopping after {} running tests", running),
+                }
+            }
+            _ => "error: expected status, abort <test> or shutdown".to_string(),
+        }
+    }
+
+    fn handle(&self, stream: UnixStream) -> std::io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            writeln!(writer, "{}", self.execute(&line?))?;
+        }
+        Ok(())
+    }
+}
+
+/// Answer commands on the control socket of this process. Only the user running the server may
+/// connect
+pub fn serve(status: ServerStatus) -> std::io::Result<()> {
+    let path = socket_path(std::process::id());
+    // Left behind by an earlier process with the same ID
+    _ = std::fs::remove_file(&path);
+    // Bound in a private directory and moved into place once it is 0600, so that nobody else
+    // can connect in between
+    let private = std::env::temp_dir().join(format!("perf-{}.d", std::process::id()));
+    _ = std::fs::remove_dir_all(&private);
+    DirBuilder::new().mode(0o700).create(&private)?;
+    let bound = private.join("control.sock");
+    let listener = UnixListener::bind(&bound).and_then(|listener| {
+        std::fs::set_permissions(&bound, Permissions::from_mode(0o600))?;
+        std::fs::rename(&bound, &path)?;
+        Ok(listener)
+    });
+    _ = std::fs::remove_dir_all(&private);
+    let listener = listener?;
+    if let Some(path) = path.to_str() {
+        _ = SOCKET_PATH.set(CString::new(path).unwrap_or_default());
+    }
+
+    thread::spawn(move || {
+        let connections = Arc::new(AtomicUsize::new(0));
+        for mut stream in listener.incoming().flatten() {
+            if connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::Relaxed);
+                _ = writeln!(stream, "error: too many control connections");
+                continue;
+            }
+            let status = status.clone();
+            let connections = connections.clone();
+            thread::spawn(move || {
+                _ = stream
+                    .set_read_timeout(Some(Duration::from_secs(IDLE_TIMEOUT)))
+                    .and_then(|_| status.handle(stream));
+                connections.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Remove the control socket if one was created. Safe in a signal handler
+pub fn remove_socket() {
+    if let Some(path) = SOCKET_PATH.get() {
+        unsafe { libc::unlink(path.as_ptr()) };
+    }
+}
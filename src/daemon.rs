@@ -0,0 +1,135 @@
+use crate::c::handle_os_result;
+use libc::*;
+use perf::exit_code;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::FromRawFd;
+use std::sync::{Mutex, OnceLock};
+
+/// Where stdout and stderr go, reopened on SIGHUP
+static LOG_FILE: OnceLock<CString> = OnceLock::new();
+static PID_FILE: OnceLock<CString> = OnceLock::new();
+/// Pipe to the parent process, which waits until the daemon has started or failed to
+static STARTUP: Mutex<Option<File>> = Mutex::new(None);
+
+fn c_path(path: &str) -> std::io::Result<CString> {
+    CString::new(path).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+/// Point stdout and stderr to the log file, or to /dev/null without one. Only uses functions
+/// which are safe in a signal handler
+fn redirect_output() -> c_int {
+    let path = match LOG_FILE.get() {
+        Some(path) => path.as_ptr(),
+        None => c"/dev/null".as_ptr(),
+    };
+    unsafe {
+        let fd = open(path, O_WRONLY | O_CREAT | O_APPEND | O_CLOEXEC, 0o644);
+        if fd < 0 {
+            return fd;
+        }
+        dup2(fd, STDOUT_FILENO);
+        dup2(fd, STDERR_FILENO);
+        close(fd)
+    }
+}
+
+extern "C" fn handle_hangup(_: c_int) {
+    // Lets log rotation move the file away and have the next line start a new one
+    redirect_output();
+}
+
+/// Detach from the terminal and keep running in the background. The working directory is kept,
+/// so that relative paths in the config still resolve. Returns only in the daemon, or with the
+/// error of the parent. The parent exits once the daemon calls `started` or `failed`, or exits
+pub fn daemonize(pid_file: Option<&str>, log_file: Option<&str>) -> std::io::Result<()> {
+    if let Some(path) = log_file {
+        _ = LOG_FILE.set(c_path(path)?);
+    }
+    // Bad paths are reported with their name here, where the error is still seen
+    for path in log_file.iter().chain(&pid_file) {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    }
+
+    let mut pipe = [0; 2];
+    unsafe { handle_os_result(pipe2(pipe.as_mut_ptr(), O_CLOEXEC))? };
+    let (read_end, write_end) = unsafe { (File::from_raw_fd(pipe[0]), File::from_raw_fd(pipe[1])) };
+
+    // Fork twice, so that the daemon is not a session leader and cannot reacquire a terminal
+    if unsafe { handle_os_result(fork())? } > 0 {
+        drop(write_end);
+        return wait_for_daemon(read_end);
+    }
+    drop(read_end);
+    *STARTUP.lock().unwrap() = Some(write_end);
+    if let Err(e) = detach(pid_file) {
+        failed(exit_code::FAILURE, &e.to_string());
+        unsafe { _exit(exit_code::FAILURE) };
+    }
+
+    Ok(())
+}
+
+/// Let the parent exit successfully, once the daemon serves
+pub fn started() {
+    STARTUP.lock().unwrap().take();
+}
+
+/// Have the parent report `message` and exit with `code`, unless the daemon has started
+pub fn failed(code: i32, message: &str) {
+    if let Some(mut pipe) = STARTUP.lock().unwrap().take() {
+        _ = pipe.write_all(&code.to_ne_bytes());
+        _ = pipe.write_all(message.as_bytes());
+    }
+}
+
+/// Exit as the daemon tells through `pipe`: with 0 if it closes the pipe without writing,
+/// otherwise with the exit code and the error it wrote. Only returns if the pipe cannot be read
+fn wait_for_daemon(mut pipe: File) -> std::io::Result<()> {
+    let mut failure = Vec::new();
+    pipe.read_to_end(&mut failure)?;
+    let Some((code, message)) = failure.split_first_chunk::<4>() else {
+        unsafe { _exit(0) }
+    };
+    eprintln!("error: {}", String::from_utf8_lossy(message));
+    unsafe { _exit(i32::from_ne_bytes(*code)) }
+}
+
+fn detach(pid_file: Option<&str>) -> std::io::Result<()> {
+    unsafe {
+        handle_os_result(setsid())?;
+        if handle_os_result(fork())? > 0 {
+            _exit(0);
+        }
+
+        let null = handle_os_result(open(c"/dev/null".as_ptr(), O_RDONLY))?;
+        handle_os_result(dup2(null, STDIN_FILENO))?;
+        close(null);
+        handle_os_result(redirect_output())?;
+
+        let mut action = std::mem::zeroed::<sigaction>();
+        action.sa_sigaction = handle_hangup as extern "C" fn(c_int) as sighandler_t;
+        action.sa_flags = SA_RESTART;
+        sigemptyset(&mut action.sa_mask);
+        handle_os_result(sigaction(SIGHUP, &action, std::ptr::null_mut()))?;
+    }
+
+    if let Some(path) = pid_file {
+        std::fs::write(path, format!("{}\n", std::process::id()))?;
+        _ = PID_FILE.set(c_path(path)?);
+    }
+
+    Ok(())
+}
+
+/// Remove the PID file if one was written. Safe in a signal handler
+pub fn remove_pid_file() {
+    if let Some(path) = PID_FILE.get() {
+        unsafe { unlink(path.as_ptr()) };
+    }
+}
@@ -0,0 +1,20 @@
+//! Exit codes of the process, so that scripts can tell failures apart.
+//! 2 is used by clap for an invalid command line
+
+/// Any failure without a more specific code
+pub const FAILURE: i32 = 1;
+/// Invalid config, baseline or event log
+pub const CONFIG: i32 = 3;
+/// Could not listen, accept or connect
+pub const CONNECTION: i32 = 4;
+/// Handshake failed or the peer sent an invalid message
+pub const PROTOCOL: i32 = 5;
+/// I/O error or idle timeout while a test was running
+pub const TRANSFER: i32 = 6;
+/// Results regressed against the baseline
+pub const THRESHOLD: i32 = 7;
+
+/// Like shells, 128 plus the signal number
+pub fn interrupted(signal: i32) -> i32 {
+    128 + signal
+}
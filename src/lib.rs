@@ -0,0 +1,67 @@
+//! Network performance tests over TCP, TLS, UDP and raw sockets. The `perf` command line runs
+//! the configs it reads, other programs can run them with this library:
+//!
+//! ```no_run
+//! use perf::program::{self, ClientConfig, Config, TcpClientConfig, TcpServerConfig};
+//! use perf::test::{TestEvent, TestOptions, TestPlan};
+//! use perf::transport::TransportMode;
+//! use std::sync::mpsc::channel;
+//!
+//! let server = program::spawn_server(
+//!     Config {
+//!         transport: "tcp-server".to_string(),
+//!         tcp_server: Some(TcpServerConfig {
+//!             address: "127.0.0.1:0".parse().unwrap(),
+//!         }),
+//!         ..Default::default()
+//!     },
+//!     TestOptions::default(),
+//! )?;
+//!
+//! let (sender, events) = channel();
+//! let mut test_options = TestOptions::default();
+//! test_options.add_listener(Box::new(sender));
+//! let mut plan = TestPlan::new(1000);
+//! plan.duration = 1.0;
+//! program::run(
+//!     Config {
+//!         transport: "tcp-client".to_string(),
+//!         tcp_client: Some(TcpClientConfig {
+//!             address: server.address(),
+//!         }),
+//!         client: Some(ClientConfig::new(TransportMode::Send, plan)),
+//!         ..Default::default()
+//!     },
+//!     test_options,
+//! )?;
+//! server.stop()?;
+//!
+//! for event in events.try_iter() {
+//!     if let TestEvent::Finish(_, summary) = event {
+//!         println!("{:?}", summary);
+//!     }
+//! }
+//! # Ok::<(), perf::program::Error>(())
+//! ```
+
+mod access;
+mod auth;
+mod c;
+pub mod compare;
+pub mod control;
+pub mod exit_code;
+mod message;
+mod metrics;
+mod policy;
+pub mod program;
+pub mod report;
+pub mod signal;
+pub mod test;
+pub mod test_format;
+pub mod transport;
+mod transports;
+
+pub use access::{AccessRules, Cidr};
+pub use auth::Credentials;
+pub use metrics::MetricsConfig;
+pub use policy::Policy;
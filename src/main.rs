@@ -1,81 +1,163 @@
+// Shared with the library, which keeps it private
+#[allow(dead_code)]
+#[path = "c.rs"]
 mod c;
 mod cli;
-mod message;
-mod program;
-mod test;
-mod test_format;
-mod transport;
-mod transports;
+mod daemon;
 
-use crate::program::Config;
-use crate::test::TestOptions;
-use crate::test_format::{Format, FormattedTestPrinter, Json, Pretty};
+use perf::program::Config;
+use perf::report::{HtmlReport, TestRecord, TestRecords};
+use perf::test::{TestListener, TestOptions};
+use perf::test_format::{Csv, FormattedTestPrinter, Influx, Json, Output, Pretty, Statsd};
+use perf::{compare, control, exit_code, program, report, signal};
 
 use std::fs::File;
 use std::io::{stdin, Read};
+use std::sync::{Arc, Mutex};
 
 type Result = std::result::Result<(), Box<dyn snafu::Error>>;
 
 fn start_handle_signals() {
-    ctrlc::set_handler(|| {
-        std::process::exit(0);
-    })
-    .unwrap();
+    signal::handle_termination(daemon::remove_pid_file).unwrap();
 }
 
-fn start<R: Read, F: Format + Clone + 'static>(reader: R, format: F) -> Result {
-    let mut deserializer = serde_json::Deserializer::from_reader(reader).into_iter();
-    let printer = FormattedTestPrinter::new(format);
-    loop {
-        // Read config from stream
-        let config: Config = if let Some(config) = deserializer.next() {
-            config?
-        } else {
-            break;
-        };
+fn start<R: Read>(reader: R, test_options: TestOptions) -> Result {
+    let deserializer = serde_json::Deserializer::from_reader(reader).into_iter();
+    // Read config from stream
+    for config in deserializer {
+        let config: Config = config?;
 
-        program::run(config, TestOptions::new(1.0, printer.clone()))?;
+        // A daemon has started once its server listens
+        program::run_listening(config, test_options.clone(), |_| daemon::started())?;
     }
 
     Ok(())
 }
 
-fn start_from_file<F: Format + Clone + 'static>(path: &str, format: F) -> Result {
+fn start_from_file(path: &str, test_options: TestOptions) -> Result {
     eprintln!("Press enter to start");
     let mut buf = [];
     _ = stdin().read(&mut buf);
     eprintln!("Started");
-    start(File::open(path)?, format)
+    start(File::open(path)?, test_options)
+}
+
+fn start_from_stdin(test_options: TestOptions) -> Result {
+    start(stdin(), test_options)
+}
+
+fn printer(format: &cli::FormatType, output: Output) -> Box<dyn TestListener + Send> {
+    match format {
+        cli::FormatType::Json => Box::new(FormattedTestPrinter::new(Json, output)),
+        cli::FormatType::Pretty => Box::new(FormattedTestPrinter::new(Pretty, output)),
+        cli::FormatType::Csv => Box::new(FormattedTestPrinter::new(Csv, output)),
+        cli::FormatType::Influx => Box::new(FormattedTestPrinter::new(Influx::new(), output)),
+        cli::FormatType::Statsd => Box::new(FormattedTestPrinter::new(Statsd::new(), output)),
+    }
+}
+
+fn read_event_log(events: &str) -> std::result::Result<Vec<TestRecord>, Box<dyn snafu::Error>> {
+    if events == "-" {
+        Ok(report::read_event_log(stdin())?)
+    } else {
+        Ok(report::read_event_log(File::open(events)?)?)
+    }
 }
 
-fn start_from_stdin<F: Format + Clone + 'static>(format: F) -> Result {
-    start(stdin(), format)
+fn start_report(events: &str, html: &str) -> Result {
+    let tests = read_event_log(events)?;
+    std::fs::write(html, report::render(&tests))?;
+    Ok(())
+}
+
+fn test_options(command: &cli::Command) -> std::result::Result<TestOptions, Box<dyn snafu::Error>> {
+    let mut test_options = TestOptions::new(command.interval);
+
+    if command.output.is_empty() {
+        let output = match &command.collector {
+            Some(collector) => Output::connect(collector)?,
+            None => Output::Stdout,
+        };
+        test_options.add_listener(printer(&command.format, output));
+    }
+    for spec in &command.output {
+        test_options.add_listener(printer(&spec.format, Output::open(&spec.path)?));
+    }
+    if let Some(html) = &command.html {
+        test_options.add_listener(Box::new(HtmlReport::new(html.clone())));
+    }
+
+    Ok(test_options)
+}
+
+fn start_tests(command: &cli::Command, test_options: TestOptions) -> Result {
+    if let Some(config) = &command.config {
+        start_from_file(config, test_options)
+    } else {
+        start_from_stdin(test_options)
+    }
+}
+
+fn start_compare(command: &cli::Command, baseline: &str, events: Option<&str>) -> Result {
+    let baseline = compare::Baseline::parse(&std::fs::read_to_string(baseline)?)?;
+    let tests = match events {
+        Some(events) => read_event_log(events)?,
+        None => {
+            let records = Arc::new(Mutex::new(TestRecords::default()));
+            let mut test_options = test_options(command)?;
+            test_options.add_listener(Box::new(records.clone()));
+            start_tests(command, test_options)?;
+            let mut records = records.lock().unwrap();
+            std::mem::take(&mut records.tests)
+        }
+    };
+    compare::compare(&baseline, &tests)?;
+    Ok(())
 }
 
 fn start_cli() -> Result {
     let command = cli::parse();
-    match command.format {
-        cli::FormatType::Json => {
-            if let Some(config) = command.config {
-                start_from_file(&config, Json)
-            } else {
-                start_from_stdin(Json)
-            }
+    match &command.action {
+        Some(cli::Action::Report { events, html }) => start_report(events, html),
+        Some(cli::Action::Compare { baseline, events }) => {
+            start_compare(&command, baseline, events.as_deref())
         }
-        cli::FormatType::Pretty => {
-            if let Some(config) = command.config {
-                start_from_file(&config, Pretty)
-            } else {
-                start_from_stdin(Pretty)
+        None => {
+            let test_options = test_options(&command)?;
+            if command.daemon {
+                daemon::daemonize(command.pid_file.as_deref(), command.log_file.as_deref())?;
             }
+            start_tests(&command, test_options)
         }
     }
 }
 
+fn error_exit_code(error: &(dyn snafu::Error + 'static)) -> i32 {
+    if let Some(e) = error.downcast_ref::<program::Error>() {
+        e.exit_code()
+    } else if let Some(e) = error.downcast_ref::<compare::Error>() {
+        e.exit_code()
+    } else if error.is::<serde_json::Error>() {
+        exit_code::CONFIG
+    } else {
+        exit_code::FAILURE
+    }
+}
+
 fn main() {
     start_handle_signals();
 
-    if let Err(e) = start_cli() {
+    let result = start_cli();
+    control::remove_socket();
+    daemon::remove_pid_file();
+    if let Err(e) = result {
         eprintln!("error: {}", e);
+        let code = error_exit_code(e.as_ref());
+        daemon::failed(code, &e.to_string());
+        std::process::exit(code);
+    }
+
+    if let Some(signal) = signal::received() {
+        std::process::exit(exit_code::interrupted(signal));
     }
 }
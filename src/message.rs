@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use snafu::{prelude::*, Backtrace};
 use std::io::{BufReader, Read, Write};
+use std::time::Instant;
 
 use crate::test::TestPlan;
 use crate::transport::{SetReadTimeout, TransportMode};
@@ -17,12 +18,72 @@ pub enum Error {
         source: bincode::Error,
         backtrace: Backtrace,
     },
+    #[snafu(display(
+        "cannot decode message, the peer may speak an incompatible protocol: {}",
+        source
+    ))]
+    Decode {
+        source: bincode::Error,
+        backtrace: Backtrace,
+    },
+    #[snafu(display(
+        "frame of {} bytes exceeds the maximum of {} bytes",
+        size,
+        MAX_FRAME_SIZE
+    ))]
+    FrameTooLarge { size: usize, backtrace: Backtrace },
+    #[snafu(display(
+        "corrupt frame: checksum {:08x} does not match {:08x}",
+        actual,
+        expected
+    ))]
+    Checksum {
+        expected: u32,
+        actual: u32,
+        backtrace: Backtrace,
+    },
     #[snafu(display("read timeout"))]
     ReadTimeout { backtrace: Backtrace },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Version of the protocol spoken by this build, increased on any change of the messages
+pub const PROTOCOL_VERSION: u32 = 4;
+/// Oldest version of the protocol this build still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 4;
+/// Optional features of the protocol supported by this build
+pub const CAPABILITIES: &[&str] = &["auth"];
+
+/// Exchanged before anything else, see `MessageWriter::write_hello`. Its layout must never
+/// change, so that any two versions can tell whether they understand each other
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hello {
+    pub version: u32,
+    pub min_version: u32,
+    pub capabilities: Vec<String>,
+}
+
+impl Hello {
+    /// What this build speaks
+    pub fn current() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    /// Whether there is a version both sides speak
+    pub fn compatible(&self, peer: &Hello) -> bool {
+        self.version.min(peer.version) >= self.min_version.max(peer.min_version)
+    }
+
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Syn {
     pub mode: TransportMode,
@@ -32,20 +93,79 @@ pub struct Syn {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SynAck {
     pub test_id: usize,
+    /// The plan the server runs, which its policy may have changed
     pub test_plan: TestPlan,
+    /// Why the plan differs from the one in `Syn`
+    pub adjustments: Vec<String>,
+}
+
+/// Sent by a server which requires authentication in answer to `Syn`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Challenge {
+    pub nonce: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Auth {
+    pub user: Option<String>,
+    /// HMAC of the nonce and the transcript of the handshake with the key of the user
+    pub signature: Vec<u8>,
+}
+
+/// What the signature of `Auth` covers besides the nonce, so that it is only valid for the
+/// handshake it answers
+pub fn transcript(client: &Hello, server: &Hello, syn: &Syn) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(&(client, server, syn))?)
+}
+
+/// Sent by the server instead of `SynAck` when it refuses to run the test
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Reject {
+    pub reason: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Message {
+    /// Must stay the first variant
+    Hello(Hello),
     Syn(Syn),
     SynAck(SynAck),
+    Challenge(Challenge),
+    Auth(Auth),
+    Reject(Reject),
 }
 
+/// A frame is the signature, the payload size and the CRC32 of the payload, both big-endian,
+/// followed by the bincode payload. The frames of `Hello`, and of the `Reject` a server sends in
+/// place of one, have no CRC32, as in the first version of the protocol
 const MESSAGE_SIGNATURE: &[u8] = b"@PERF@";
+/// Largest payload accepted, far above any message so that a corrupt size cannot exhaust memory
+pub const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// Fails reads with `WouldBlock` once the deadline has passed, however slowly the peer sends
+struct DeadlineReader<R> {
+    read: R,
+    deadline: Option<Instant>,
+}
+
+impl<R: Read + SetReadTimeout> Read for DeadlineReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            // 0 would disable the timeout
+            let milliseconds = remaining.as_millis().max(1) as u64;
+            self.read.set_read_timeout(Some(milliseconds))?;
+        }
+        self.read.read(buffer)
+    }
+}
 
 pub struct MessageReader<R: Read, T: SetReadTimeout> {
     timeout: T,
-    reader: BufReader<R>,
+    reader: BufReader<DeadlineReader<R>>,
     buffer: Vec<u8>,
 }
 
@@ -53,37 +173,57 @@ impl<R: Read + SetReadTimeout + Clone> MessageReader<R, R> {
     pub fn new(read: R) -> Self {
         Self {
             timeout: read.clone(),
-            reader: BufReader::new(read.clone()),
+            reader: BufReader::new(DeadlineReader {
+                read: read.clone(),
+                deadline: None,
+            }),
             buffer: vec![0; 1050],
         }
     }
 
     pub fn read(&mut self) -> Result<Message> {
-        loop {
-            // Read until signature come
-            'outer: loop {
-                let mut signature_buffer = [0u8];
-                for &byte in MESSAGE_SIGNATURE {
-                    self.reader.read_exact(&mut signature_buffer)?;
-                    if signature_buffer[0] != byte {
-                        continue 'outer;
-                    }
+        self.read_frame(true)
+    }
+
+    /// Read the message which starts the handshake, a `Hello`, or a `Reject` from a server which
+    /// refuses the client. Fails with `ReadTimeout` once `deadline` has passed
+    pub fn read_hello(&mut self, deadline: Instant) -> Result<Message> {
+        self.with_deadline(deadline, |reader| reader.read_frame(false))
+    }
+
+    fn read_frame(&mut self, checksum: bool) -> Result<Message> {
+        // Read until signature come, skipping leftovers such as datagrams of a previous test
+        'outer: loop {
+            let mut signature_buffer = [0u8];
+            for &byte in MESSAGE_SIGNATURE {
+                self.reader.read_exact(&mut signature_buffer)?;
+                if signature_buffer[0] != byte {
+                    continue 'outer;
                 }
-                break;
             }
+            break;
+        }
 
-            // Read message size
-            let mut size_buffer = [0u8; 4];
-            self.reader.read_exact(&mut size_buffer)?;
-            let size = u32::from_be_bytes(size_buffer) as usize;
+        // Read message size and checksum
+        let mut size_buffer = [0u8; 4];
+        self.reader.read_exact(&mut size_buffer)?;
+        let size = u32::from_be_bytes(size_buffer) as usize;
+        ensure!(size <= MAX_FRAME_SIZE, FrameTooLargeSnafu { size });
+        let mut expected = None;
+        if checksum {
+            let mut checksum_buffer = [0u8; 4];
+            self.reader.read_exact(&mut checksum_buffer)?;
+            expected = Some(u32::from_be_bytes(checksum_buffer));
+        }
 
-            // Read message
-            self.buffer.resize(size, 0);
-            self.reader.read_exact(&mut self.buffer)?;
-            if let Ok(message) = bincode::deserialize(&self.buffer) {
-                break Ok(message);
-            }
+        // Read message
+        self.buffer.resize(size, 0);
+        self.reader.read_exact(&mut self.buffer)?;
+        if let Some(expected) = expected {
+            let actual = crc32fast::hash(&self.buffer);
+            ensure!(actual == expected, ChecksumSnafu { expected, actual });
         }
+        bincode::deserialize(&self.buffer).context(DecodeSnafu)
     }
 
     /// Read message until the function `f` returns `Some<T>`
@@ -97,28 +237,34 @@ impl<R: Read + SetReadTimeout + Clone> MessageReader<R, R> {
         Ok(value)
     }
 
-    pub fn read_until_timeout<T>(
+    /// Like `read_until`, but fails with `ReadTimeout` once `deadline` has passed
+    pub fn read_until_deadline<T>(
         &mut self,
         f: fn(message: Message) -> Option<T>,
-        milliseconds: u64,
+        deadline: Instant,
     ) -> Result<T> {
-        self.timeout.set_read_timeout(Some(milliseconds))?;
-        let value = loop {
-            let message = self.read().map_err(|e| match e {
-                // Convert IO TimedOut error to ReadTimeout error
-                Error::IO { source, backtrace } => match source.kind() {
-                    std::io::ErrorKind::WouldBlock => ReadTimeoutSnafu.build(),
-                    _ => Error::IO { source, backtrace },
-                },
-                _ => e,
-            })?;
+        self.with_deadline(deadline, |reader| reader.read_until(f))
+    }
 
-            if let Some(value) = f(message) {
-                break value;
-            }
-        };
+    fn with_deadline<T>(
+        &mut self,
+        deadline: Instant,
+        read: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.reader.get_mut().deadline = Some(deadline);
+        let result = read(self).map_err(|e| match e {
+            // Convert IO TimedOut error to ReadTimeout error
+            Error::IO { source, backtrace } => match source.kind() {
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                    ReadTimeoutSnafu.build()
+                }
+                _ => Error::IO { source, backtrace },
+            },
+            _ => e,
+        });
+        self.reader.get_mut().deadline = None;
         self.timeout.set_read_timeout(None)?;
-        Ok(value)
+        result
     }
 }
 
@@ -136,6 +282,20 @@ impl<W: Write> MessageWriter<W> {
     }
 
     pub fn write(&mut self, message: Message) -> Result<()> {
+        self.write_frame(message, true)
+    }
+
+    /// Start the handshake in the frame every version of the protocol reads
+    pub fn write_hello(&mut self, hello: Hello) -> Result<()> {
+        self.write_frame(Message::Hello(hello), false)
+    }
+
+    /// Refuse the client in place of a `Hello`, in the frame it reads the answer to its own from
+    pub fn refuse(&mut self, reason: String) -> Result<()> {
+        self.write_frame(Message::Reject(Reject { reason }), false)
+    }
+
+    fn write_frame(&mut self, message: Message, checksum: bool) -> Result<()> {
         self.buffer.clear();
 
         // Write signature
@@ -143,14 +303,152 @@ impl<W: Write> MessageWriter<W> {
 
         // Write size
         let message_size = bincode::serialized_size(&message)? as usize;
+        ensure!(
+            message_size <= MAX_FRAME_SIZE,
+            FrameTooLargeSnafu { size: message_size }
+        );
         self.buffer
             .extend_from_slice(&(message_size as u32).to_be_bytes());
 
-        // Write message
-        let len = self.buffer.len();
-        self.buffer.resize(self.buffer.len() + message_size, 0);
+        // Write message, then its checksum in front of it
+        let checksum_offset = self.buffer.len();
+        let len = if checksum {
+            checksum_offset + 4
+        } else {
+            checksum_offset
+        };
+        self.buffer.resize(len + message_size, 0);
         bincode::serialize_into(&mut self.buffer[len..], &message)?;
-        self.writer.write(&self.buffer)?;
+        if checksum {
+            let checksum = crc32fast::hash(&self.buffer[len..]);
+            self.buffer[checksum_offset..len].copy_from_slice(&checksum.to_be_bytes());
+        }
+
+        // A frame is only ever written whole
+        self.writer.write_all(&self.buffer)?;
+        self.writer.flush()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::TestPlan;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// Bytes received from the peer
+    #[derive(Clone)]
+    struct Input(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+    impl Read for Input {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().read(buffer)
+        }
+    }
+
+    impl SetReadTimeout for Input {
+        fn set_read_timeout(&mut self, _: Option<u64>) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn reader(bytes: Vec<u8>) -> MessageReader<Input, Input> {
+        MessageReader::new(Input(Rc::new(RefCell::new(Cursor::new(bytes)))))
+    }
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(1)
+    }
+
+    fn syn() -> Message {
+        Message::Syn(Syn {
+            mode: TransportMode::Send,
+            options: TestPlan::new(1000),
+        })
+    }
+
+    #[test]
+    fn hello_round_trip() {
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_hello(Hello::current()).unwrap();
+        let Message::Hello(hello) = reader(writer.writer).read_hello(deadline()).unwrap() else {
+            panic!("no hello");
+        };
+        assert_eq!(hello.version, PROTOCOL_VERSION);
+        assert!(hello.compatible(&Hello::current()));
+    }
+
+    #[test]
+    fn hello_frame_has_no_checksum() {
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_hello(Hello::current()).unwrap();
+        let frame = writer.writer;
+        let size = u32::from_be_bytes(frame[6..10].try_into().unwrap()) as usize;
+        assert_eq!(frame.len(), MESSAGE_SIGNATURE.len() + 4 + size);
+    }
+
+    #[test]
+    fn refusal_is_read_in_place_of_hello() {
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.refuse("denied".to_string()).unwrap();
+        assert!(matches!(
+            reader(writer.writer).read_hello(deadline()),
+            Ok(Message::Reject(Reject { reason })) if reason == "denied"
+        ));
+    }
+
+    #[test]
+    fn version_mismatch_is_incompatible() {
+        let old = Hello {
+            version: MIN_PROTOCOL_VERSION - 1,
+            min_version: 1,
+            capabilities: Vec::new(),
+        };
+        let newer = Hello {
+            version: PROTOCOL_VERSION + 2,
+            min_version: PROTOCOL_VERSION + 1,
+            capabilities: Vec::new(),
+        };
+        let overlapping = Hello {
+            version: PROTOCOL_VERSION + 1,
+            min_version: 1,
+            capabilities: Vec::new(),
+        };
+        let current = Hello::current();
+        assert!(!current.compatible(&old) && !old.compatible(&current));
+        assert!(!current.compatible(&newer) && !newer.compatible(&current));
+        assert!(current.compatible(&overlapping) && overlapping.compatible(&current));
+    }
+
+    #[test]
+    fn corrupt_frame_fails_checksum() {
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write(syn()).unwrap();
+        let mut frame = writer.writer;
+        let last = frame.len() - 1;
+        frame[last] ^= 0xff;
+        assert!(matches!(reader(frame).read(), Err(Error::Checksum { .. })));
+    }
+
+    #[test]
+    fn reader_skips_to_next_frame() {
+        let mut writer = MessageWriter::new(b"leftover".to_vec());
+        writer.write(syn()).unwrap();
+        assert!(matches!(reader(writer.writer).read(), Ok(Message::Syn(_))));
+    }
+
+    #[test]
+    fn oversized_frame_is_refused() {
+        let mut frame = MESSAGE_SIGNATURE.to_vec();
+        frame.extend_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_be_bytes());
+        frame.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            reader(frame).read(),
+            Err(Error::FrameTooLarge { .. })
+        ));
+    }
+}
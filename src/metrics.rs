@@ -0,0 +1,155 @@
+use crate::test::{TestData, TestInterval, TestListener, TestSummary};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddrV4, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Deserialize)]
+pub struct MetricsConfig {
+    /// Where the `/metrics` endpoint listens
+    pub address: SocketAddrV4,
+}
+
+#[derive(Default)]
+struct Metrics {
+    completed_tests: u64,
+    active_tests: u64,
+    transferred_bytes: u64,
+    errors: u64,
+    /// Bits per second of the last test, by client address
+    last_bits_per_second: BTreeMap<Ipv4Addr, f64>,
+}
+
+impl Metrics {
+    /// Render in the Prometheus text exposition format
+    fn render(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, r#type: &str, help: &str, value: f64| {
+            _ = writeln!(text, "# HELP {} {}", name, help);
+            _ = writeln!(text, "# TYPE {} {}", name, r#type);
+            _ = writeln!(text, "{} {}", name, value);
+        };
+        metric(
+            "perf_completed_tests_total",
+            "counter",
+            "Number of finished tests",
+            self.completed_tests as f64,
+        );
+        metric(
+            "perf_active_tests",
+            "gauge",
+            "Number of running tests",
+            self.active_tests as f64,
+        );
+        metric(
+            "perf_transferred_bytes_total",
+            "counter",
+            "Bytes sent or received by all tests",
+            self.transferred_bytes as f64,
+        );
+        metric(
+            "perf_errors_total",
+            "counter",
+            "Number of failed tests and connections",
+            self.errors as f64,
+        );
+
+        _ = writeln!(
+            text,
+            "# HELP perf_last_test_bits_per_second Average bitrate of the last test of a client"
+        );
+        _ = writeln!(text, "# TYPE perf_last_test_bits_per_second gauge");
+        for (client, bits_per_second) in &self.last_bits_per_second {
+            _ = writeln!(
+                text,
+                "perf_last_test_bits_per_second{{client=\"{}\"}} {}",
+                client, bits_per_second
+            );
+        }
+
+        text
+    }
+}
+
+/// Collects metrics of tests
+pub struct MetricsListener {
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl TestListener for MetricsListener {
+    fn on_start(&mut self, _: &TestData) {
+        self.metrics.lock().unwrap().active_tests += 1;
+    }
+
+    fn on_finish(&mut self, data: &TestData, summary: &TestSummary) {
+        let mut metrics = self.metrics.lock().unwrap();
+        metrics.active_tests -= 1;
+        metrics.completed_tests += 1;
+        metrics.transferred_bytes += data.total_transfer as u64;
+        if summary.error.is_some() {
+            metrics.errors += 1;
+        }
+        if let Some(peer) = data.peer {
+            let bits_per_second = if summary.elapsed > 0.0 {
+                (data.total_transfer * 8) as f64 / summary.elapsed
+            } else {
+                0.0
+            };
+            metrics
+                .last_bits_per_second
+                .insert(*peer.ip(), bits_per_second);
+        }
+    }
+
+    /// Bytes are counted once a test finishes, without the omitted period
+    fn on_report(&mut self, _: &TestData, _: &TestInterval) {}
+
+    /// Only tests which could not start, the others finish with an error
+    fn on_error(&mut self, _: &str) {
+        self.metrics.lock().unwrap().errors += 1;
+    }
+}
+
+/// Start serving `/metrics` over HTTP and return the listener which feeds it
+pub fn serve(config: &MetricsConfig) -> std::io::Result<MetricsListener> {
+    let listener = TcpListener::bind(config.address)?;
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+
+    let served_metrics = metrics.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            if let Err(e) = respond(stream, &served_metrics) {
+                eprintln!("metrics error: {}", e);
+            }
+        }
+    });
+
+    Ok(MetricsListener { metrics })
+}
+
+fn respond(mut stream: TcpStream, metrics: &Mutex<Metrics>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    // Only the request line matters
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = metrics.lock().unwrap().render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes())
+}
@@ -0,0 +1,228 @@
+use crate::test::TestPlan;
+use crate::transport::TransportMode;
+use serde::Deserialize;
+
+/// Limits a server puts on the test plans of its clients. Plans exceeding a limit are clamped
+/// to it, plans which cannot be clamped are rejected
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Longest test in seconds, the omitted warm-up included
+    pub max_duration: Option<f64>,
+    /// Fastest test in bits per second
+    pub max_bitrate: Option<f64>,
+    pub max_packet_size: Option<usize>,
+    /// Modes clients may request, all if empty
+    #[serde(default)]
+    pub modes: Vec<TransportMode>,
+}
+
+impl Policy {
+    /// Clamp `plan` to the limits and describe every change, or tell why the test is rejected.
+    /// `max_packet_size` is the hard limit of the transport, whatever the policy
+    pub fn apply(
+        &self,
+        mode: &TransportMode,
+        plan: &mut TestPlan,
+        max_packet_size: usize,
+    ) -> Result<Vec<String>, String> {
+        let mut changes = Vec::new();
+
+        if !self.modes.is_empty() && !self.modes.contains(mode) {
+            return Err(format!("mode {} is not allowed", mode));
+        }
+
+        // A NaN or negative value would slip through every comparison with a limit
+        check_positive("duration", plan.duration)?;
+        if let Some(time_limit) = plan.time_limit {
+            check_positive("time_limit", time_limit)?;
+        }
+        if let Some(bitrate) = plan.bitrate {
+            check_positive("bitrate", bitrate)?;
+        }
+        if !plan.omit.is_finite() || plan.omit < 0.0 {
+            return Err(format!("omit must not be negative, got {}", plan.omit));
+        }
+        // Checked before the buffers of the test are allocated with this size
+        if plan.packet_size == 0 || plan.packet_size > max_packet_size {
+            return Err(format!(
+                "packet size must be between 1 and {}, got {}",
+                max_packet_size, plan.packet_size
+            ));
+        }
+
+        if let Some(max) = self.max_duration {
+            if plan.omit >= max {
+                return Err(format!(
+                    "omit of {}s leaves nothing of max_duration {}s",
+                    plan.omit, max
+                ));
+            }
+            let limit = max - plan.omit;
+            if plan.bytes.is_some() || plan.packets.is_some() {
+                if plan.time_limit.is_none_or(|time_limit| time_limit > limit) {
+                    plan.time_limit = Some(limit);
+                    changes.push(format!("stopped after {}s by max_duration", limit));
+                }
+            } else if plan.duration > limit {
+                changes.push(format!(
+                    "duration {}s reduced to {}s by max_duration",
+                    plan.duration, limit
+                ));
+                plan.duration = limit;
+            }
+        }
+
+        if let Some(max) = self.max_bitrate {
+            match plan.bitrate {
+                Some(bitrate) if bitrate <= max => {}
+                Some(bitrate) => {
+                    changes.push(format!(
+                        "bitrate {}bit/s reduced to {}bit/s by max_bitrate",
+                        bitrate, max
+                    ));
+                    plan.bitrate = Some(max);
+                }
+                None => {
+                    changes.push(format!("paced to {}bit/s by max_bitrate", max));
+                    plan.bitrate = Some(max);
+                }
+            }
+        }
+
+        if let Some(max) = self.max_packet_size {
+            if plan.packet_size > max {
+                changes.push(format!(
+                    "packet size {} reduced to {} by max_packet_size",
+                    plan.packet_size, max
+                ));
+                plan.packet_size = max;
+            }
+        }
+
+        Ok(changes)
+    }
+}
+
+fn check_positive(name: &str, value: f64) -> Result<(), String> {
+    if value.is_finite() && value > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{} must be a positive number, got {}", name, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: usize = 64 * 1024;
+
+    fn plan() -> TestPlan {
+        TestPlan::new(1000)
+    }
+
+    fn limited() -> Policy {
+        Policy {
+            max_duration: Some(10.0),
+            max_bitrate: Some(1e9),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rejects_unusable_values() {
+        let mut plans = Vec::new();
+        for duration in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            plans.push(TestPlan { duration, ..plan() });
+        }
+        for time_limit in [0.0, -1.0, f64::NAN] {
+            plans.push(TestPlan {
+                bytes: Some(1000),
+                time_limit: Some(time_limit),
+                ..plan()
+            });
+        }
+        for bitrate in [0.0, -1.0, f64::NAN] {
+            plans.push(TestPlan {
+                bitrate: Some(bitrate),
+                ..plan()
+            });
+        }
+        for omit in [-1.0, f64::NAN] {
+            plans.push(TestPlan { omit, ..plan() });
+        }
+        for packet_size in [0, MAX + 1, usize::MAX] {
+            plans.push(TestPlan {
+                packet_size,
+                ..plan()
+            });
+        }
+
+        for policy in [Policy::default(), limited()] {
+            for plan in &plans {
+                let mut plan = plan.clone();
+                assert!(
+                    policy.apply(&TransportMode::Send, &mut plan, MAX).is_err(),
+                    "{:?} accepted",
+                    plan
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn clamps_to_limits() {
+        let mut plan = TestPlan {
+            duration: 30.0,
+            omit: 2.0,
+            bitrate: Some(1e10),
+            ..plan()
+        };
+        let changes = limited()
+            .apply(&TransportMode::Send, &mut plan, MAX)
+            .unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(plan.duration, 8.0);
+        assert_eq!(plan.bitrate, Some(1e9));
+    }
+
+    #[test]
+    fn keeps_plans_within_limits() {
+        let mut plan = TestPlan {
+            omit: 0.0,
+            bitrate: Some(1e6),
+            ..plan()
+        };
+        let changes = limited()
+            .apply(&TransportMode::Send, &mut plan, MAX)
+            .unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn clamps_packet_size() {
+        let policy = Policy {
+            max_packet_size: Some(1500),
+            ..Default::default()
+        };
+        let mut plan = TestPlan {
+            packet_size: MAX,
+            ..plan()
+        };
+        let changes = policy.apply(&TransportMode::Send, &mut plan, MAX).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(plan.packet_size, 1500);
+    }
+
+    #[test]
+    fn rejects_omit_beyond_max_duration() {
+        let mut plan = TestPlan {
+            omit: 10.0,
+            ..plan()
+        };
+        assert!(limited()
+            .apply(&TransportMode::Send, &mut plan, MAX)
+            .is_err());
+    }
+}
@@ -1,50 +1,128 @@
+use crate::access::AccessRules;
+use crate::auth;
+use crate::auth::Credentials;
+use crate::control;
+use crate::control::ServerStatus;
+use crate::exit_code;
 use crate::message;
 use crate::message::*;
-use crate::test::{Test, TestData, TestOptions, TestPlan};
+use crate::metrics;
+use crate::metrics::MetricsConfig;
+use crate::policy::Policy;
+use crate::signal;
+use crate::signal::GracefulStop;
+use crate::test::{Test, TestData, TestOptions, TestPlan, MIN_REPORT_INTERVAL};
 use crate::transport::*;
 use crate::transports::*;
 use serde::Deserialize;
-use snafu::{prelude::*, Backtrace, ErrorCompat, GenerateImplicitData};
+use snafu::{prelude::*, Backtrace, ErrorCompat, IntoError};
+use std::io::ErrorKind;
 use std::net::{Ipv4Addr, SocketAddrV4};
+use std::sync::mpsc::channel;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-#[derive(Deserialize)]
+/// One server or client, as read from the config stream
+#[derive(Deserialize, Default)]
 pub struct Config {
-    /// tcp-server/client, raw-server/client, zero-copy-server/client
-    transport: String,
-    tcp_server: Option<TcpServerConfig>,
-    tcp_client: Option<TcpClientConfig>,
-    udp_server: Option<TcpServerConfig>,
-    udp_client: Option<TcpClientConfig>,
-    raw_server: Option<RawServerConfig>,
-    raw_client: Option<RawClientConfig>,
-    client: Option<ClientConfig>,
+    /// tcp-server/client, tls-server/client, udp-server/client, raw-server/client
+    pub transport: String,
+    pub tcp_server: Option<TcpServerConfig>,
+    pub tcp_client: Option<TcpClientConfig>,
+    pub tls_server: Option<TlsServerConfig>,
+    pub tls_client: Option<TlsClientConfig>,
+    pub udp_server: Option<TcpServerConfig>,
+    pub udp_client: Option<TcpClientConfig>,
+    pub raw_server: Option<RawServerConfig>,
+    pub raw_client: Option<RawClientConfig>,
+    pub client: Option<ClientConfig>,
+    pub server: Option<ServerConfig>,
+    /// Serve metrics of the tests for Prometheus
+    pub metrics: Option<MetricsConfig>,
 }
 
 #[derive(Deserialize)]
-struct TcpServerConfig {
-    address: SocketAddrV4,
+pub struct TcpServerConfig {
+    pub address: SocketAddrV4,
 }
 
 #[derive(Deserialize)]
-struct TcpClientConfig {
-    address: SocketAddrV4,
+pub struct TcpClientConfig {
+    pub address: SocketAddrV4,
+}
+
+#[derive(Deserialize)]
+pub struct TlsServerConfig {
+    pub address: SocketAddrV4,
+    /// PEM files, a self-signed certificate is generated if not given
+    pub certificate: Option<String>,
+    pub private_key: Option<String>,
+    /// Names such as "TLS13_AES_128_GCM_SHA256", all supported suites if empty
+    #[serde(default)]
+    pub cipher_suites: Vec<String>,
+    /// Let the kernel encrypt and decrypt after the handshake, if it supports it
+    #[serde(default)]
+    pub ktls: bool,
+}
+
+#[derive(Deserialize)]
+pub struct TlsClientConfig {
+    pub address: SocketAddrV4,
+    #[serde(default = "default_server_name")]
+    pub server_name: String,
+    /// PEM file to verify the server certificate with, which is not verified if not given
+    pub ca_certificate: Option<String>,
+    #[serde(default)]
+    pub cipher_suites: Vec<String>,
+    #[serde(default)]
+    pub ktls: bool,
+}
+
+fn default_server_name() -> String {
+    "localhost".to_string()
 }
 
 #[derive(Deserialize)]
-struct RawServerConfig {
-    interface: String,
+pub struct RawServerConfig {
+    pub interface: String,
 }
 
 #[derive(Deserialize)]
-struct RawClientConfig {
-    interface: String,
-    address: Ipv4Addr,
+pub struct RawClientConfig {
+    pub interface: String,
+    pub address: Ipv4Addr,
 }
 
 #[derive(Deserialize)]
-struct ClientConfig {
-    mode: TransportMode,
-    test_plan: TestPlan,
+pub struct ClientConfig {
+    pub mode: TransportMode,
+    pub test_plan: TestPlan,
+    /// Answers the challenge of a server which requires authentication
+    pub auth: Option<Credentials>,
+}
+
+impl ClientConfig {
+    pub fn new(mode: TransportMode, test_plan: TestPlan) -> Self {
+        Self {
+            mode,
+            test_plan,
+            auth: None,
+        }
+    }
+}
+
+/// Shared by all server transports
+#[derive(Deserialize, Default)]
+pub struct ServerConfig {
+    /// Clients must authenticate with one of these keys if any is given
+    #[serde(default)]
+    pub auth: Vec<Credentials>,
+    /// Limits on the test plans of clients
+    #[serde(default)]
+    pub policy: Policy,
+    /// Networks of clients to accept and to refuse
+    #[serde(flatten)]
+    pub access: AccessRules,
 }
 
 #[derive(Snafu, Debug)]
@@ -54,6 +132,16 @@ pub enum Error {
         source: std::io::Error,
         backtrace: Backtrace,
     },
+    #[snafu(display("connection error: {}", source))]
+    Connection {
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+    #[snafu(display("transfer error: {}", source))]
+    Transfer {
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
     #[snafu(display("invalid config: {}", message))]
     InvalidConfig {
         message: String,
@@ -64,10 +152,107 @@ pub enum Error {
         #[snafu(backtrace)]
         source: message::Error,
     },
+    #[snafu(display("interrupted by signal {}", signal))]
+    Interrupted { signal: i32, backtrace: Backtrace },
+    #[snafu(display("aborted through the control socket"))]
+    Aborted { backtrace: Backtrace },
+    #[snafu(display("the server was stopped"))]
+    Stopped { backtrace: Backtrace },
+    #[snafu(display("nothing transferred for {} seconds", seconds))]
+    IdleTimeout { seconds: f64, backtrace: Backtrace },
+    #[snafu(display("the server requires authentication, but client.auth is not configured"))]
+    AuthenticationRequired { backtrace: Backtrace },
+    #[snafu(display("authentication failed for user {:?}", user))]
+    AuthenticationFailed {
+        user: Option<String>,
+        backtrace: Backtrace,
+    },
+    #[snafu(display(
+        "incompatible protocol: this side speaks versions {}-{}, the peer {}-{}",
+        local.min_version,
+        local.version,
+        peer.min_version,
+        peer.version
+    ))]
+    Incompatible {
+        local: Hello,
+        peer: Hello,
+        backtrace: Backtrace,
+    },
+    #[snafu(display("no protocol handshake from the peer, it may run an older version"))]
+    NoHello { backtrace: Backtrace },
+    #[snafu(display("the client does not support {}", capability))]
+    MissingCapability {
+        capability: String,
+        backtrace: Backtrace,
+    },
+    #[snafu(display("client {} is not allowed", address))]
+    AccessDenied {
+        address: SocketAddrV4,
+        backtrace: Backtrace,
+    },
+    #[snafu(display("test rejected by policy: {}", reason))]
+    PolicyViolation {
+        reason: String,
+        backtrace: Backtrace,
+    },
+    #[snafu(display("rejected by server: {}", reason))]
+    Rejected {
+        reason: String,
+        backtrace: Backtrace,
+    },
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Exit code of the process when a run ends with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::IO { .. } => exit_code::FAILURE,
+            Error::InvalidConfig { .. } => exit_code::CONFIG,
+            Error::Connection { .. } => exit_code::CONNECTION,
+            // Such as a failed write of the Syn, or the peer closing during the handshake
+            Error::Message {
+                source: message::Error::IO { .. },
+            } => exit_code::CONNECTION,
+            Error::Message { .. }
+            | Error::AuthenticationRequired { .. }
+            | Error::AuthenticationFailed { .. }
+            | Error::Rejected { .. }
+            | Error::PolicyViolation { .. }
+            | Error::AccessDenied { .. }
+            | Error::Incompatible { .. }
+            | Error::NoHello { .. }
+            | Error::MissingCapability { .. } => exit_code::PROTOCOL,
+            Error::Transfer { .. } | Error::IdleTimeout { .. } => exit_code::TRANSFER,
+            Error::Aborted { .. } | Error::Stopped { .. } => exit_code::FAILURE,
+            Error::Interrupted { signal, .. } => exit_code::interrupted(*signal),
+        }
+    }
+}
+
+/// Milliseconds a receiver waits for data before checking whether it should stop
+const POLL_INTERVAL: u64 = 100;
+/// Milliseconds a server waits before accepting again after a failure
+const ACCEPT_RETRY_INTERVAL: u64 = 1000;
+/// Milliseconds a client waits for the server to answer its `Hello`
+const HELLO_TIMEOUT: u64 = 5000;
+/// Milliseconds a client has to complete the handshake, so that a silent one cannot block the
+/// server
+const HANDSHAKE_TIMEOUT: u64 = 5000;
+/// Seconds a sender may keep sending after the end of its test
+const RECEIVE_GRACE: f64 = 1.0;
+
+fn invalid_tls_config<T>(result: std::io::Result<T>) -> Result<T> {
+    result.map_err(|e| {
+        InvalidConfigSnafu {
+            message: format!("Invalid TLS config: {}", e),
+        }
+        .build()
+    })
+}
+
 fn missing_field(field: &'static str) -> Result<()> {
     Err(InvalidConfigSnafu {
         message: format!("The field \"{}\" is required", field),
@@ -75,13 +260,122 @@ fn missing_field(field: &'static str) -> Result<()> {
     .build())
 }
 
+/// How a server is run, by the command line or in a thread of another program
+struct ServerSetup {
+    status: ServerStatus,
+    /// Answer commands on the control socket of the process
+    control_socket: bool,
+    /// End the running test on a termination signal of the process, rather than on
+    /// `ServerStatus::stop`
+    signals: bool,
+    /// Called with the address clients connect to, once the server listens
+    listening: Option<Box<dyn FnOnce(SocketAddrV4) + Send>>,
+}
+
+/// Run one server or client until it ends. A server answers commands on the control socket of
+/// the process
 pub fn run(config: Config, test_options: TestOptions) -> Result<()> {
+    let setup = ServerSetup {
+        status: ServerStatus::default(),
+        control_socket: true,
+        signals: true,
+        listening: None,
+    };
+    run_with(config, test_options, setup)
+}
+
+/// Like `run`, and calls `listening` with the address clients connect to once a server listens
+pub fn run_listening(
+    config: Config,
+    test_options: TestOptions,
+    listening: impl FnOnce(SocketAddrV4) + Send + 'static,
+) -> Result<()> {
+    let setup = ServerSetup {
+        status: ServerStatus::default(),
+        control_socket: true,
+        signals: true,
+        listening: Some(Box::new(listening)),
+    };
+    run_with(config, test_options, setup)
+}
+
+/// A server running in a thread of this process, see `spawn_server`
+pub struct ServerHandle {
+    address: SocketAddrV4,
+    status: ServerStatus,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl ServerHandle {
+    /// Address clients connect to
+    pub fn address(&self) -> SocketAddrV4 {
+        self.address
+    }
+
+    /// Stop accepting clients, end the running test and wait for the server to return
+    pub fn stop(self) -> Result<()> {
+        self.status.stop();
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+/// Start a server in a new thread and return once it listens. Configure port 0 to have the
+/// system choose a free one, and read it from `ServerHandle::address`. Unlike `run`, no control
+/// socket is created and signals are left to the process, so that it may embed several servers
+pub fn spawn_server(config: Config, test_options: TestOptions) -> Result<ServerHandle> {
+    ensure!(
+        config.transport.ends_with("-server"),
+        InvalidConfigSnafu {
+            message: format!("\"{}\" is not a server transport", config.transport),
+        }
+    );
+
+    let status = ServerStatus::default();
+    let (listening, address) = channel();
+    let setup = ServerSetup {
+        status: status.clone(),
+        control_socket: false,
+        signals: false,
+        listening: Some(Box::new(move |address| _ = listening.send(address))),
+    };
+    let thread = thread::spawn(move || run_with(config, test_options, setup));
+    match address.recv() {
+        Ok(address) => Ok(ServerHandle {
+            address,
+            status,
+            thread,
+        }),
+        // The server ended before listening, such as with an invalid config
+        Err(_) => match thread.join() {
+            Ok(result) => result.and_then(|_| {
+                InvalidConfigSnafu {
+                    message: "The server ended before listening",
+                }
+                .fail()
+            }),
+            Err(panic) => std::panic::resume_unwind(panic),
+        },
+    }
+}
+
+fn run_with(config: Config, mut test_options: TestOptions, setup: ServerSetup) -> Result<()> {
+    if let Some(metrics_config) = &config.metrics {
+        let listener = metrics::serve(metrics_config)?;
+        test_options.add_listener(Box::new(listener));
+    }
+    let server_config = config.server.unwrap_or_default();
+
     match config.transport.as_str() {
         "tcp-server" => match config.tcp_server {
             None => missing_field("tcp_server"),
             Some(tcp_server_config) => Ok(start_server(
                 TcpServer::new(tcp_server_config.address),
+                server_config,
                 test_options,
+                setup,
             )?),
         },
         "tcp-client" => match config.client {
@@ -95,11 +389,45 @@ pub fn run(config: Config, test_options: TestOptions) -> Result<()> {
                 )?),
             },
         },
+        "tls-server" => match config.tls_server {
+            None => missing_field("tls_server"),
+            Some(tls_server_config) => Ok(start_server(
+                invalid_tls_config(TlsServer::new(
+                    tls_server_config.address,
+                    tls_server_config.certificate.as_deref(),
+                    tls_server_config.private_key.as_deref(),
+                    &tls_server_config.cipher_suites,
+                    tls_server_config.ktls,
+                ))?,
+                server_config,
+                test_options,
+                setup,
+            )?),
+        },
+        "tls-client" => match config.client {
+            None => missing_field("client_config"),
+            Some(client_config) => match config.tls_client {
+                None => missing_field("tls_client"),
+                Some(tls_client_config) => Ok(start_client(
+                    invalid_tls_config(TlsClient::new(
+                        tls_client_config.address,
+                        &tls_client_config.server_name,
+                        tls_client_config.ca_certificate.as_deref(),
+                        &tls_client_config.cipher_suites,
+                        tls_client_config.ktls,
+                    ))?,
+                    client_config,
+                    test_options,
+                )?),
+            },
+        },
         "udp-server" => match config.udp_server {
             None => missing_field("udp_server"),
             Some(udp_server_config) => Ok(start_server(
                 UdpServer::new(udp_server_config.address),
+                server_config,
                 test_options,
+                setup,
             )?),
         },
         "udp-client" => match config.client {
@@ -117,7 +445,9 @@ pub fn run(config: Config, test_options: TestOptions) -> Result<()> {
             None => missing_field("raw_server"),
             Some(raw_server_config) => Ok(start_server(
                 RawServer::new(raw_server_config.interface),
+                server_config,
                 test_options,
+                setup,
             )?),
         },
         "raw-client" => match config.client {
@@ -141,140 +471,501 @@ pub fn run(config: Config, test_options: TestOptions) -> Result<()> {
     }
 }
 
+/// Load the key of each credentials, so that invalid keys are found before any test
+fn load_keys(credentials: &[Credentials]) -> Result<Vec<(Option<String>, Vec<u8>)>> {
+    credentials
+        .iter()
+        .map(|credentials| match credentials.key() {
+            Ok(key) => Ok((credentials.user.clone(), key)),
+            Err(e) => InvalidConfigSnafu {
+                message: format!("Invalid auth key: {}", e),
+            }
+            .fail(),
+        })
+        .collect()
+}
+
 fn start_server<S: Server<L, Conn>, L: Listener<Conn>, Conn: Connection + 'static>(
     server: S,
+    server_config: ServerConfig,
     test_options: TestOptions,
+    setup: ServerSetup,
 ) -> Result<()> {
+    let keys = load_keys(&server_config.auth)?;
+    let policy = &server_config.policy;
+    let access = &server_config.access;
     let mut test_id = 0;
-    let listener = server.listen()?;
+    let listener = server.listen().context(ConnectionSnafu)?;
+    let listeners = test_options.clone();
+    let status = setup.status;
+    if setup.control_socket {
+        if let Err(e) = control::serve(status.clone()) {
+            eprintln!("Control socket not available: {}", e);
+        }
+    }
+    if let Some(listening) = setup.listening {
+        listening(listener.local_address().context(ConnectionSnafu)?);
+    }
 
     loop {
-        let connection = listener.accept()?;
+        if status.is_shutting_down() {
+            return Ok(());
+        }
+
+        // Wake up regularly to notice a shutdown
+        let accepted = listener
+            .set_accept_timeout(Some(POLL_INTERVAL))
+            .and_then(|_| listener.accept());
+        let (mut connection, address) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                continue
+            }
+            Err(e) => {
+                // Such as running out of file descriptors, which may pass
+                eprintln!("error: cannot accept a client: {}", e);
+                thread::sleep(Duration::from_millis(ACCEPT_RETRY_INTERVAL));
+                continue;
+            }
+        };
         let test_options = test_options.clone();
         let test_id = &mut test_id;
+        let keys = &keys;
+        let status = &status;
 
-        if let Err(e) = (move || -> Result<()> {
+        let handshake = (move || -> Result<(Conn, Test, TransportMode, usize)> {
+            // Told without reading anything, so that denied clients cannot reach the handshake
+            if !access.permits(*address.ip()) {
+                MessageWriter::new(connection.clone())
+                    .refuse(format!("address {} is not allowed", address.ip()))?;
+                return AccessDeniedSnafu { address }.fail();
+            }
+
+            // Accepted sockets inherit the timeout of the listener
+            connection.set_read_timeout(None).context(ConnectionSnafu)?;
+            let deadline = Instant::now() + Duration::from_millis(HANDSHAKE_TIMEOUT);
             let mut reader = MessageReader::new(connection.clone());
             let mut writer = MessageWriter::new(connection.clone());
 
-            let syn = reader.read_until(|m| match m {
-                Message::Syn(syn) => Some(syn),
-                _ => None,
-            })?;
+            let Message::Hello(hello) = reader.read_hello(deadline)? else {
+                return NoHelloSnafu.fail();
+            };
+            // Answer even when incompatible, so that the client can tell why
+            writer.write_hello(Hello::current())?;
+            check_compatible(hello.clone())?;
+
+            let syn = reader.read_until_deadline(
+                |m| match m {
+                    Message::Syn(syn) => Some(syn),
+                    _ => None,
+                },
+                deadline,
+            )?;
+
+            if !keys.is_empty() {
+                if !hello.supports("auth") {
+                    writer.write(Message::Reject(Reject {
+                        reason: "authentication required".to_string(),
+                    }))?;
+                    return MissingCapabilitySnafu { capability: "auth" }.fail();
+                }
+                let transcript = message::transcript(&hello, &Hello::current(), &syn)?;
+                authenticate(&mut reader, &mut writer, keys, &transcript, deadline)?;
+            }
+
+            let mut final_options = syn.options.clone();
+            let adjustments =
+                match policy.apply(&syn.mode, &mut final_options, Conn::max_packet_size()) {
+                    Ok(adjustments) => adjustments,
+                    Err(reason) => {
+                        writer.write(Message::Reject(Reject {
+                            reason: reason.clone(),
+                        }))?;
+                        return PolicyViolationSnafu { reason }.fail();
+                    }
+                };
 
             *test_id += 1;
             let test_id = *test_id;
 
-            let final_options = syn.options.clone();
             // Send syn ack
             let syn_ack = Message::SynAck(SynAck {
                 test_id,
                 test_plan: final_options.clone(),
+                adjustments,
             });
             writer.write(syn_ack)?;
+            connection.start_transfer().context(ConnectionSnafu)?;
 
             // Start test
             let test = Test::new(
-                TestData::new(test_id, final_options.clone()),
+                TestData::new(
+                    test_id,
+                    final_options.clone(),
+                    syn.mode.opposite(),
+                    connection.transport(),
+                    Some(address),
+                ),
                 test_options.clone(),
             );
-            match syn.mode {
-                TransportMode::Send => start_receiver(connection, test),
-                TransportMode::Receive => start_sender(connection, test),
-            }?;
-
-            Ok(())
-        })() {
-            eprintln!("error: {}", e);
-            if let Some(backtrace) = e.backtrace() {
-                eprintln!("{}", backtrace);
+            Ok((connection, test, syn.mode, test_id))
+        })();
+        let (connection, test, mode, id) = match handshake {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // No test was started, so no finish event tells about the error
+                listeners.notify(|listener| listener.on_error(&e.to_string()));
+                print_error(&e);
+                continue;
             }
+        };
+
+        status.begin(id, test.handle());
+        let server = (!setup.signals).then_some(status);
+        let result = match mode {
+            TransportMode::Send => start_receiver(connection, test, server),
+            TransportMode::Receive => start_sender(connection, test, server),
+        };
+        status.end(id);
+        if let Err(e) = result {
+            match e {
+                Error::Interrupted { .. } => return Err(e),
+                Error::Stopped { .. } => return Ok(()),
+                _ => {}
+            }
+            // The finish event of the test carries the error
+            print_error(&e);
         }
     }
 }
 
+fn print_error(e: &Error) {
+    eprintln!("error: {}", e);
+    if let Some(backtrace) = e.backtrace() {
+        eprintln!("{}", backtrace);
+    }
+}
+
+fn check_compatible(peer: Hello) -> Result<()> {
+    let local = Hello::current();
+    ensure!(local.compatible(&peer), IncompatibleSnafu { local, peer });
+    Ok(())
+}
+
+/// Challenge the client to prove that it has one of the keys, and reject it otherwise. The
+/// signature must cover the `transcript` of this handshake
+fn authenticate<Conn: Connection>(
+    reader: &mut MessageReader<Conn, Conn>,
+    writer: &mut MessageWriter<Conn>,
+    keys: &[(Option<String>, Vec<u8>)],
+    transcript: &[u8],
+    deadline: Instant,
+) -> Result<()> {
+    let nonce = auth::nonce()?;
+    writer.write(Message::Challenge(Challenge {
+        nonce: nonce.clone(),
+    }))?;
+
+    let response = reader.read_until_deadline(
+        |m| match m {
+            Message::Auth(auth) => Some(auth),
+            _ => None,
+        },
+        deadline,
+    )?;
+    let authenticated = keys.iter().any(|(user, key)| {
+        *user == response.user && auth::verify(key, &nonce, transcript, &response.signature)
+    });
+    if !authenticated {
+        writer.write(Message::Reject(Reject {
+            reason: "authentication failed".to_string(),
+        }))?;
+        return AuthenticationFailedSnafu {
+            user: response.user,
+        }
+        .fail();
+    }
+
+    Ok(())
+}
+
 fn start_client<C: Client<Conn>, Conn: Connection + 'static>(
     client: C,
     client_config: ClientConfig,
     test_options: TestOptions,
 ) -> Result<()> {
-    let connection = client.connect()?;
+    let mut test_plan = client_config.test_plan.clone();
+    match test_plan.report_interval {
+        Some(interval) if interval != 0.0 && interval < MIN_REPORT_INTERVAL => {
+            return InvalidConfigSnafu {
+                message: format!(
+                    "The report interval must be 0 or at least {} seconds",
+                    MIN_REPORT_INTERVAL
+                ),
+            }
+            .fail();
+        }
+        Some(_) => {}
+        // Let the server report at the same cadence
+        None => test_plan.report_interval = Some(test_options.report_interval),
+    }
+    ensure!(
+        test_plan.bitrate.is_none_or(|bitrate| bitrate > 0.0),
+        InvalidConfigSnafu {
+            message: "The bitrate must be positive"
+        }
+    );
+
+    let key = match &client_config.auth {
+        Some(credentials) => Some(credentials.key().map_err(|e| {
+            InvalidConfigSnafu {
+                message: format!("Invalid auth key: {}", e),
+            }
+            .build()
+        })?),
+        None => None,
+    };
+
+    let mut connection = client.connect().context(ConnectionSnafu)?;
     let mut reader = MessageReader::new(connection.clone());
     let mut writer = MessageWriter::new(connection.clone());
 
+    // Agree on the protocol before anything else
+    let client_hello = Hello::current();
+    writer.write_hello(client_hello.clone())?;
+    let hello = match reader
+        .read_hello(Instant::now() + Duration::from_millis(HELLO_TIMEOUT))
+        .map_err(|e| match e {
+            message::Error::ReadTimeout { .. } => NoHelloSnafu.build(),
+            e => e.into(),
+        })? {
+        Message::Hello(hello) => hello,
+        Message::Reject(reject) => {
+            return RejectedSnafu {
+                reason: reject.reason,
+            }
+            .fail()
+        }
+        _ => return NoHelloSnafu.fail(),
+    };
+    check_compatible(hello.clone())?;
+
     // Send Syn
-    writer.write(Message::Syn(Syn {
+    let syn = Syn {
         mode: client_config.mode.clone(),
-        options: client_config.test_plan.clone(),
-    }))?;
+        options: test_plan,
+    };
+    let transcript = message::transcript(&client_hello, &hello, &syn)?;
+    writer.write(Message::Syn(syn))?;
 
-    // Wait for SynAck
-    let syn_ack = reader.read_until(|m| match m {
-        Message::SynAck(syn_ack) => Some(syn_ack),
-        _ => None,
-    })?;
+    // Wait for SynAck, answering the challenge of the server if it requires authentication
+    let syn_ack = loop {
+        let message = reader.read_until(|m| match m {
+            Message::SynAck(_) | Message::Challenge(_) | Message::Reject(_) => Some(m),
+            _ => None,
+        })?;
+        match message {
+            Message::SynAck(syn_ack) => break syn_ack,
+            Message::Challenge(challenge) => {
+                let (Some(credentials), Some(key)) = (&client_config.auth, &key) else {
+                    return AuthenticationRequiredSnafu.fail();
+                };
+                writer.write(Message::Auth(Auth {
+                    user: credentials.user.clone(),
+                    signature: auth::sign(key, &challenge.nonce, &transcript),
+                }))?;
+            }
+            Message::Reject(reject) => {
+                return RejectedSnafu {
+                    reason: reject.reason,
+                }
+                .fail()
+            }
+            _ => unreachable!(),
+        }
+    };
+    connection.start_transfer().context(ConnectionSnafu)?;
+    for adjustment in &syn_ack.adjustments {
+        eprintln!("Server adjusted the test plan: {}", adjustment);
+    }
 
     let test = Test::new(
-        TestData::new(syn_ack.test_id, syn_ack.test_plan),
+        TestData::new(
+            syn_ack.test_id,
+            syn_ack.test_plan,
+            client_config.mode.clone(),
+            connection.transport(),
+            connection.peer_address().ok(),
+        ),
         test_options,
     );
 
     match client_config.mode {
-        TransportMode::Send => start_sender(connection, test),
-        TransportMode::Receive => start_receiver(connection, test),
+        TransportMode::Send => start_sender(connection, test, None),
+        TransportMode::Receive => start_receiver(connection, test, None),
     }
 }
 
-fn start_sender<Conn: Connection + 'static>(mut connection: Conn, mut test: Test) -> Result<()> {
-    let buffer = vec![0; test.data.plan.packet_size];
+/// Finish the test early if `server` was stopped, or without one if a termination signal was
+/// received. Fail it if it was aborted
+fn check_interrupted(test: &mut Test, server: Option<&ServerStatus>) -> Result<()> {
+    if test.is_aborted() {
+        let error = AbortedSnafu.build();
+        test.fail(error.to_string());
+        return Err(error);
+    }
+    let result = match server {
+        Some(server) if server.is_stopped() => StoppedSnafu.fail(),
+        Some(_) => return Ok(()),
+        None => match signal::received() {
+            Some(signal) => InterruptedSnafu { signal }.fail(),
+            None => return Ok(()),
+        },
+    };
+    test.finish();
+    result
+}
+
+/// Fail the test with a transfer error, so that its listeners are told
+fn fail_transfer<T>(test: &mut Test, source: std::io::Error) -> Result<T> {
+    let error = TransferSnafu.into_error(source);
+    test.fail(error.to_string());
+    Err(error)
+}
+
+/// Fail the test if nothing was transferred for longer than the idle timeout of the plan
+fn check_idle(test: &mut Test, last_transferred: Instant) -> Result<()> {
+    match test.plan.idle_timeout {
+        Some(seconds) if last_transferred.elapsed().as_secs_f64() > seconds => {
+            let error = IdleTimeoutSnafu { seconds }.build();
+            test.fail(error.to_string());
+            Err(error)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Send until the plan is done. `server` is the server running the test, if it is embedded
+fn start_sender<Conn: Connection + 'static>(
+    mut connection: Conn,
+    mut test: Test,
+    server: Option<&ServerStatus>,
+) -> Result<()> {
+    let buffer = vec![0; test.plan.packet_size];
+    let _graceful_stop = server.is_none().then(GracefulStop::new);
+    // A receiver which stops reading blocks the writes, wake up to check the idle timeout
+    connection
+        .set_write_timeout(Some(POLL_INTERVAL))
+        .context(TransferSnafu)?;
     test.start();
+    let started = Instant::now();
+    // Since when writes time out, if they do
+    let mut blocked_since = None;
+    let mut sent = 0;
     loop {
-        let written = match connection.write(&buffer) {
+        check_interrupted(&mut test, server)?;
+
+        let size = test.next_packet_size();
+        let written = match connection.write(&buffer[..size]) {
             Ok(written) => written,
             Err(e) => match e.kind() {
-                std::io::ErrorKind::ConnectionReset => break,
+                std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::BrokenPipe => break,
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                    check_idle(&mut test, *blocked_since.get_or_insert_with(Instant::now))?;
+                    continue;
+                }
+                std::io::ErrorKind::Interrupted => continue,
                 _ => {
                     if let Some(raw_error) = e.raw_os_error() {
                         // No buffer space available
                         if raw_error == 105 {
                             continue;
                         } else {
-                            return Err(Error::IO {
-                                source: e,
-                                backtrace: Backtrace::generate(),
-                            });
+                            return fail_transfer(&mut test, e);
                         }
                     } else {
-                        return Err(Error::IO {
-                            source: e,
-                            backtrace: Backtrace::generate(),
-                        });
+                        return fail_transfer(&mut test, e);
                     }
                 }
             },
         };
         test.transferred(written);
+        blocked_since = None;
 
-        // Break if time is over
-        if test.elapsed().as_secs_f64() > test.data.plan.duration {
+        // Break if time is over or everything is sent
+        if test.is_finished() {
             break;
         }
+
+        // Wait until the bitrate allows the next packet
+        sent += written;
+        if let Some(bitrate) = test.plan.bitrate {
+            let due = Duration::try_from_secs_f64(sent as f64 * 8.0 / bitrate);
+            if let Some(wait) = due.ok().and_then(|due| due.checked_sub(started.elapsed())) {
+                thread::sleep(wait);
+            }
+        }
     }
     test.finish();
 
     Ok(())
 }
 
-fn start_receiver<Conn: Connection>(mut connection: Conn, mut test: Test) -> Result<()> {
+/// Receive until the sender is done. `server` is the server running the test, if it is embedded
+fn start_receiver<Conn: Connection>(
+    mut connection: Conn,
+    mut test: Test,
+    server: Option<&ServerStatus>,
+) -> Result<()> {
     let header_size = Conn::header_size();
-    let mut buffer = vec![0; header_size + test.data.plan.packet_size];
+    let mut buffer = vec![0; header_size + test.plan.packet_size];
+    let _graceful_stop = server.is_none().then(GracefulStop::new);
+    connection
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .context(TransferSnafu)?;
     test.start();
+    let started = Instant::now();
+    // The plan is enforced on senders which ignore it, with some slack for their late start
+    let deadline = test.plan.time_allowed().and_then(|seconds| {
+        Duration::try_from_secs_f64(test.plan.omit + seconds + RECEIVE_GRACE).ok()
+    });
+    let mut last_received = Instant::now();
+    let mut received = 0;
     loop {
-        let read = connection.read(&mut buffer)?;
+        check_interrupted(&mut test, server)?;
+
+        if deadline.is_some_and(|deadline| started.elapsed() > deadline) {
+            break;
+        }
+
+        // Stop reading while the sender is ahead of the bitrate, which holds it back
+        if let Some(bitrate) = test.plan.bitrate {
+            let due = Duration::try_from_secs_f64(received as f64 * 8.0 / bitrate);
+            if let Some(wait) = due.ok().and_then(|due| due.checked_sub(started.elapsed())) {
+                thread::sleep(wait.min(Duration::from_millis(POLL_INTERVAL)));
+                last_received = Instant::now();
+                continue;
+            }
+        }
+
+        let read = match connection.read(&mut buffer) {
+            Ok(read) => read,
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::Interrupted => {
+                    check_idle(&mut test, last_received)?;
+                    continue;
+                }
+                _ => return fail_transfer(&mut test, e),
+            },
+        };
+        last_received = Instant::now();
         if read == header_size {
             break;
         }
         test.transferred(read);
+        received += read;
     }
     test.finish();
 
@@ -0,0 +1,291 @@
+use crate::test::{TestData, TestInterval, TestListener, TestSummary};
+use crate::test_format::{format_number, format_statistics, EventType};
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::io::Read;
+
+/// Everything known about one test
+pub struct TestRecord {
+    pub data: TestData,
+    /// Only intervals after the omitted period
+    pub intervals: Vec<TestInterval>,
+    pub summary: Option<TestSummary>,
+}
+
+/// An event as written by the `Json` format
+#[derive(Deserialize)]
+struct RecordedEvent {
+    r#type: EventType,
+    data: TestData,
+    interval: Option<TestInterval>,
+    summary: Option<TestSummary>,
+}
+
+/// Records the events of all tests
+#[derive(Default)]
+pub struct TestRecords {
+    pub tests: Vec<TestRecord>,
+}
+
+impl TestRecords {
+    fn record(&mut self, data: &TestData) -> Option<&mut TestRecord> {
+        self.tests
+            .iter_mut()
+            .rev()
+            .find(|record| record.data.id == data.id)
+    }
+}
+
+impl TestListener for TestRecords {
+    fn on_start(&mut self, data: &TestData) {
+        self.tests.push(TestRecord {
+            data: data.clone(),
+            intervals: Vec::new(),
+            summary: None,
+        });
+    }
+
+    fn on_finish(&mut self, data: &TestData, summary: &TestSummary) {
+        if let Some(record) = self.record(data) {
+            record.data = data.clone();
+            record.summary = Some(summary.clone());
+        }
+    }
+
+    fn on_report(&mut self, data: &TestData, interval: &TestInterval) {
+        if data.omitted {
+            return;
+        }
+        if let Some(record) = self.record(data) {
+            record.intervals.push(interval.clone());
+        }
+    }
+}
+
+/// Rewrites a self-contained HTML report each time a test finishes
+pub struct HtmlReport {
+    path: String,
+    records: TestRecords,
+}
+
+impl HtmlReport {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            records: TestRecords::default(),
+        }
+    }
+}
+
+impl TestListener for HtmlReport {
+    fn on_start(&mut self, data: &TestData) {
+        self.records.on_start(data);
+    }
+
+    fn on_finish(&mut self, data: &TestData, summary: &TestSummary) {
+        self.records.on_finish(data, summary);
+        if let Err(e) = std::fs::write(&self.path, render(&self.records.tests)) {
+            eprintln!("report error: {}", e);
+        }
+    }
+
+    fn on_report(&mut self, data: &TestData, interval: &TestInterval) {
+        self.records.on_report(data, interval);
+    }
+}
+
+/// Read the tests from a JSON event log
+pub fn read_event_log<R: Read>(reader: R) -> serde_json::Result<Vec<TestRecord>> {
+    let mut records = TestRecords::default();
+    for event in serde_json::Deserializer::from_reader(reader).into_iter::<RecordedEvent>() {
+        let event = event?;
+        match (event.r#type, &event.interval, &event.summary) {
+            (EventType::Start, _, _) => records.on_start(&event.data),
+            (EventType::Report, Some(interval), _) => records.on_report(&event.data, interval),
+            (EventType::Finish, _, Some(summary)) => records.on_finish(&event.data, summary),
+            _ => {}
+        }
+    }
+    Ok(records.tests)
+}
+
+/// Render a whole HTML document
+pub fn render(tests: &[TestRecord]) -> String {
+    let mut html = String::new();
+    html.push_str(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>perf report</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; color: #222; }\n\
+         table { border-collapse: collapse; margin-bottom: 1em; }\n\
+         th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }\n\
+         .error { color: #b00; }\n\
+         svg text { font-size: 11px; fill: #444; }\n\
+         </style>\n</head>\n<body>\n<h1>perf report</h1>\n",
+    );
+    if tests.is_empty() {
+        html.push_str("<p>No tests.</p>\n");
+    }
+    for test in tests {
+        render_test(&mut html, test);
+    }
+    html.push_str(
+        "<p>Loss and round-trip time are not measured by the transports, so they are not charted.</p>\n\
+         </body>\n</html>\n",
+    );
+    html
+}
+
+fn render_test(html: &mut String, test: &TestRecord) {
+    let data = &test.data;
+    _ = writeln!(html, "<h2>Test #{}</h2>", data.id);
+
+    _ = writeln!(html, "<h3>Summary</h3>\n<table>");
+    row(html, "Mode", &data.mode.to_string());
+    row(html, "Transport", &data.transport);
+    if let Some(peer) = data.peer {
+        row(html, "Peer", &peer.to_string());
+    }
+    row(html, "Bytes", &data.total_transfer.to_string());
+    row(html, "Packets", &data.total_packets.to_string());
+    match &test.summary {
+        Some(summary) => {
+            row(html, "Elapsed", &format!("{:.2}s", summary.elapsed));
+            row(
+                html,
+                "Throughput",
+                &format_statistics(&summary.bits_per_second, "bit/s"),
+            );
+            row(
+                html,
+                "Packet rate",
+                &format_statistics(&summary.packets_per_second, "packets/s"),
+            );
+            if let Some(error) = &summary.error {
+                _ = writeln!(
+                    html,
+                    "<tr><th>Error</th><td class=\"error\">{}</td></tr>",
+                    escape(error)
+                );
+            }
+        }
+        None => row(html, "Result", "Not finished"),
+    }
+    html.push_str("</table>\n");
+
+    _ = writeln!(html, "<h3>Configuration</h3>\n<table>");
+    if let Ok(serde_json::Value::Object(plan)) = serde_json::to_value(&data.plan) {
+        for (key, value) in plan {
+            let value = match value {
+                serde_json::Value::Null => "-".to_string(),
+                value => value.to_string(),
+            };
+            row(html, &key, &value);
+        }
+    }
+    html.push_str("</table>\n");
+
+    let throughput: Vec<_> = test
+        .intervals
+        .iter()
+        .map(|interval| (interval.start, interval.end, interval.bits_per_second))
+        .collect();
+    let packet_rate: Vec<_> = test
+        .intervals
+        .iter()
+        .map(|interval| (interval.start, interval.end, interval.packets_per_second))
+        .collect();
+    chart(html, "Throughput", "bit/s", &throughput);
+    chart(html, "Packet rate", "packets/s", &packet_rate);
+}
+
+fn row(html: &mut String, name: &str, value: &str) {
+    _ = writeln!(
+        html,
+        "<tr><th>{}</th><td>{}</td></tr>",
+        escape(name),
+        escape(value)
+    );
+}
+
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 240.0;
+/// Room for the axis labels
+const CHART_MARGIN: f64 = 60.0;
+const CHART_TICKS: usize = 5;
+
+/// Step chart of `(start, end, value)` intervals
+fn chart(html: &mut String, title: &str, unit: &str, intervals: &[(f64, f64, f64)]) {
+    _ = writeln!(html, "<h3>{} ({})</h3>", escape(title), unit);
+    if intervals.is_empty() {
+        html.push_str("<p>No interval reports.</p>\n");
+        return;
+    }
+
+    let max_time = intervals.iter().map(|&(_, end, _)| end).fold(0.0, f64::max);
+    let max_value = intervals
+        .iter()
+        .map(|&(_, _, value)| value)
+        .fold(0.0, f64::max);
+    let max_time = if max_time > 0.0 { max_time } else { 1.0 };
+    let max_value = if max_value > 0.0 {
+        max_value * 1.1
+    } else {
+        1.0
+    };
+
+    let plot_width = CHART_WIDTH - 2.0 * CHART_MARGIN;
+    let plot_height = CHART_HEIGHT - CHART_MARGIN;
+    let x = |time: f64| CHART_MARGIN + time / max_time * plot_width;
+    let y = |value: f64| CHART_MARGIN / 2.0 + plot_height - value / max_value * plot_height;
+
+    _ = writeln!(
+        html,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT
+    );
+    for tick in 0..=CHART_TICKS {
+        let ratio = tick as f64 / CHART_TICKS as f64;
+        let value = max_value * ratio;
+        let time = max_time * ratio;
+        _ = writeln!(
+            html,
+            "<line x1=\"{:.1}\" y1=\"{y:.1}\" x2=\"{:.1}\" y2=\"{y:.1}\" stroke=\"#eee\"/>\
+             <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\
+             <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{:.2}s</text>",
+            x(0.0),
+            x(max_time),
+            x(0.0) - 4.0,
+            y(value) + 4.0,
+            format_number(value),
+            x(time),
+            y(0.0) + 16.0,
+            time,
+            y = y(value),
+        );
+    }
+
+    let mut points = String::new();
+    for &(start, end, value) in intervals {
+        _ = write!(
+            points,
+            "{:.1},{:.1} {:.1},{:.1} ",
+            x(start),
+            y(value),
+            x(end),
+            y(value)
+        );
+    }
+    _ = writeln!(
+        html,
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"#1f77b4\" stroke-width=\"2\"/>",
+        points.trim_end()
+    );
+    html.push_str("</svg>\n");
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
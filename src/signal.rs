@@ -0,0 +1,65 @@
+use crate::c::*;
+use crate::control;
+use libc::*;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::OnceLock;
+
+/// Number of the first termination signal received, 0 if none
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+/// Whether a running test can be stopped gracefully
+static STOPPABLE: AtomicBool = AtomicBool::new(false);
+/// Run before exiting on a signal
+static CLEANUP: OnceLock<fn()> = OnceLock::new();
+
+extern "C" fn handle_signal(signal: c_int) {
+    // Exit immediately if nothing can be stopped or on the second signal
+    if !STOPPABLE.load(Ordering::SeqCst) || RECEIVED.swap(signal, Ordering::SeqCst) != 0 {
+        control::remove_socket();
+        if let Some(cleanup) = CLEANUP.get() {
+            cleanup();
+        }
+        unsafe { _exit(128 + signal) };
+    }
+}
+
+/// Install handlers of SIGINT and SIGTERM, which are followed by the tests of this process but
+/// not by servers started with `program::spawn_server`. `cleanup` runs before exiting on a signal
+/// and must be safe in a signal handler
+pub fn handle_termination(cleanup: fn()) -> std::io::Result<()> {
+    _ = CLEANUP.set(cleanup);
+    unsafe {
+        let mut action = std::mem::zeroed::<sigaction>();
+        action.sa_sigaction = handle_signal as extern "C" fn(c_int) as sighandler_t;
+        action.sa_flags = SA_RESTART;
+        sigemptyset(&mut action.sa_mask);
+        for signal in [SIGINT, SIGTERM] {
+            handle_os_result(sigaction(signal, &action, std::ptr::null_mut()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the termination signal if one was received
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// While alive, the first termination signal only asks the running test to stop
+pub(crate) struct GracefulStop;
+
+impl GracefulStop {
+    pub fn new() -> Self {
+        STOPPABLE.store(true, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for GracefulStop {
+    fn drop(&mut self) {
+        STOPPABLE.store(false, Ordering::SeqCst);
+    }
+}
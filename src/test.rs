@@ -1,39 +1,102 @@
-use chrono::{DateTime, Utc};
+use crate::transport::TransportMode;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::time::Duration;
+use std::cell::Cell;
+use std::net::SocketAddrV4;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 /// Shared between client and server
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestPlan {
-    /// Duration in seconds
+    /// Duration in seconds, ignored when `bytes` or `packets` is given
+    #[serde(default = "default_duration")]
     pub duration: f64,
+    /// Total bytes to transfer
+    pub bytes: Option<usize>,
+    /// Total packets to send
+    pub packets: Option<usize>,
     /// Per packet byte size
     pub packet_size: usize,
+    /// Seconds at the beginning of the test which are not counted
+    #[serde(default)]
+    pub omit: f64,
+    /// Seconds without sending or receiving anything after which the test fails
+    pub idle_timeout: Option<f64>,
+    /// Seconds between reports, 0 for only the summary. Filled in by the client, so that both
+    /// sides report at the same cadence
+    pub report_interval: Option<f64>,
+    /// Bits per second the sender paces itself to, as fast as possible if not given
+    pub bitrate: Option<f64>,
+    /// Seconds after which a test stops even if `bytes` or `packets` are not reached
+    pub time_limit: Option<f64>,
 }
 
+fn default_duration() -> f64 {
+    10.0
+}
+
+impl TestPlan {
+    /// A test of the default duration, with nothing else limited
+    pub fn new(packet_size: usize) -> Self {
+        Self {
+            duration: default_duration(),
+            bytes: None,
+            packets: None,
+            packet_size,
+            omit: 0.0,
+            idle_timeout: None,
+            report_interval: None,
+            bitrate: None,
+            time_limit: None,
+        }
+    }
+
+    /// Seconds the test runs after the omitted period at most, if it is limited by time
+    pub fn time_allowed(&self) -> Option<f64> {
+        if self.bytes.is_none() && self.packets.is_none() {
+            Some(self.duration)
+        } else {
+            self.time_limit
+        }
+    }
+}
+
+/// Shortest supported report interval in seconds
+pub const MIN_REPORT_INTERVAL: f64 = 0.01;
+
 #[derive(Clone)]
 pub struct TestOptions {
-    /// Report interval in seconds
+    /// Report interval in seconds used when the plan has none, 0 disables reports
     pub report_interval: f64,
-    pub event_handler: Rc<RefCell<Box<dyn TestListener>>>,
+    /// Every listener receives every event
+    pub listeners: Vec<Arc<Mutex<Box<dyn TestListener + Send>>>>,
 }
 
 impl Default for TestOptions {
     fn default() -> Self {
-        Self::new(f64::MAX, EmptyTestEventHandler)
+        Self::new(0.0)
     }
 }
 
 impl TestOptions {
-    pub fn new<EventHandler: TestListener + 'static>(
-        report_interval: f64,
-        event_handler: EventHandler,
-    ) -> Self {
+    pub fn new(report_interval: f64) -> Self {
         Self {
             report_interval,
-            event_handler: Rc::new(RefCell::new(Box::new(event_handler))),
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn add_listener(&mut self, listener: Box<dyn TestListener + Send>) {
+        self.listeners.push(Arc::new(Mutex::new(listener)));
+    }
+
+    /// Call `f` with each listener in order
+    pub fn notify(&self, mut f: impl FnMut(&mut dyn TestListener)) {
+        for listener in &self.listeners {
+            f(listener.lock().unwrap().as_mut());
         }
     }
 }
@@ -41,93 +104,659 @@ impl TestOptions {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestData {
     pub id: usize,
+    /// What this side of the test does
+    pub mode: TransportMode,
+    pub transport: String,
+    /// Address of the other side of the test
+    pub peer: Option<SocketAddrV4>,
     pub total_transfer: usize,
     pub total_packets: usize,
+    /// Whether the test is still in the omitted warm-up period
+    pub omitted: bool,
     pub plan: TestPlan,
 
-    #[serde(skip_serializing)]
-    start_time: DateTime<Utc>,
-    #[serde(skip_serializing)]
+    /// Monotonic, so that the clock of the system stepping does not affect durations
+    #[serde(skip, default = "Instant::now")]
+    start_time: Instant,
+    #[serde(skip)]
     report_count: usize,
 }
 
 impl TestData {
-    pub fn new(id: usize, plan: TestPlan) -> Self {
+    pub fn new(
+        id: usize,
+        plan: TestPlan,
+        mode: TransportMode,
+        transport: &str,
+        peer: Option<SocketAddrV4>,
+    ) -> Self {
         Self {
             id,
+            mode,
+            transport: transport.to_string(),
+            peer,
             total_transfer: 0,
             total_packets: 0,
-            start_time: Utc::now(),
+            omitted: false,
+            start_time: Instant::now(),
             report_count: 0,
             plan,
         }
     }
 
     pub fn elapsed(&self) -> Duration {
-        let now = Utc::now();
-        (now - self.start_time).to_std().unwrap()
+        self.start_time.elapsed()
+    }
+}
+
+/// Statistics of a value measured per report interval
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Statistics {
+    pub average: f64,
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+    pub standard_deviation: f64,
+}
+
+impl Statistics {
+    pub fn new(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+
+        let count = sorted.len();
+        let average = sorted.iter().sum::<f64>() / count as f64;
+        let median = if count.is_multiple_of(2) {
+            (sorted[count / 2 - 1] + sorted[count / 2]) / 2.0
+        } else {
+            sorted[count / 2]
+        };
+        let variance = sorted
+            .iter()
+            .map(|value| (value - average).powi(2))
+            .sum::<f64>()
+            / count as f64;
+
+        Self {
+            average,
+            min: sorted[0],
+            max: sorted[count - 1],
+            median,
+            standard_deviation: variance.sqrt(),
+        }
+    }
+}
+
+/// Sent with the finish event
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TestSummary {
+    /// Seconds actually measured, excluding the omitted period
+    pub elapsed: f64,
+    pub bits_per_second: Statistics,
+    pub packets_per_second: Statistics,
+    /// Why the test failed, if it did
+    pub error: Option<String>,
+}
+
+/// Sent with each report event
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TestInterval {
+    /// Seconds since the test started
+    pub start: f64,
+    /// Seconds since the test started
+    pub end: f64,
+    pub bytes: usize,
+    pub packets: usize,
+    pub bits_per_second: f64,
+    pub packets_per_second: f64,
+}
+
+/// Trailing intervals shorter than this ratio of the report interval are not reported
+const MINIMUM_TRAILING_INTERVAL: f64 = 0.1;
+
+/// Cumulative counters at a point of time
+#[derive(Clone, Copy)]
+struct Sample {
+    elapsed: f64,
+    total_transfer: usize,
+    total_packets: usize,
+}
+
+impl Sample {
+    const ZERO: Sample = Sample {
+        elapsed: 0.0,
+        total_transfer: 0,
+        total_packets: 0,
+    };
+}
+
+/// Added to by the transferring thread for every packet, without locking the state
+#[derive(Default)]
+struct Counters {
+    transfer: AtomicUsize,
+    packets: AtomicUsize,
+}
+
+/// Where counting starts once the omitted period is over
+#[derive(Clone, Copy)]
+struct Counting {
+    since: Instant,
+    /// Counted during the omitted period
+    omitted_transfer: usize,
+    omitted_packets: usize,
+}
+
+impl Counting {
+    fn total_transfer(&self, counters: &Counters) -> usize {
+        counters.transfer.load(Ordering::Relaxed) - self.omitted_transfer
+    }
+
+    fn total_packets(&self, counters: &Counters) -> usize {
+        counters.packets.load(Ordering::Relaxed) - self.omitted_packets
+    }
+}
+
+/// Shared between the test and its reporter thread
+struct TestState {
+    data: TestData,
+    counters: Arc<Counters>,
+    /// Counted during the omitted period, which the totals of `data` leave out
+    omitted_transfer: usize,
+    omitted_packets: usize,
+    /// Where the current interval started
+    interval_start: Sample,
+    bits_per_second: Vec<f64>,
+    packets_per_second: Vec<f64>,
+}
+
+impl TestState {
+    /// Copy the counters into the totals of `data`
+    fn sync(&mut self) {
+        self.data.total_transfer =
+            self.counters.transfer.load(Ordering::Relaxed) - self.omitted_transfer;
+        self.data.total_packets =
+            self.counters.packets.load(Ordering::Relaxed) - self.omitted_packets;
+    }
+
+    /// Update the totals and end the omitted period if its time is over
+    fn update_omission(&mut self) {
+        self.sync();
+        if self.data.omitted && self.data.elapsed().as_secs_f64() >= self.data.plan.omit {
+            // Discard everything counted so far and restart the clock
+            self.omitted_transfer += self.data.total_transfer;
+            self.omitted_packets += self.data.total_packets;
+            self.data.total_transfer = 0;
+            self.data.total_packets = 0;
+            self.data.start_time = Instant::now();
+            self.data.report_count = 0;
+            self.data.omitted = false;
+            self.interval_start = Sample::ZERO;
+        }
+    }
+
+    /// Seconds until the next report or the end of the omitted period
+    fn until_next_event(&self, report_interval: f64) -> f64 {
+        let elapsed = self.data.elapsed().as_secs_f64();
+        let next_report = ((self.data.report_count + 1) as f64) * report_interval - elapsed;
+        if self.data.omitted {
+            next_report.min(self.data.plan.omit - elapsed)
+        } else {
+            next_report
+        }
+    }
+
+    fn should_report(&mut self, report_interval: f64) -> bool {
+        let elapsed = self.data.elapsed().as_secs_f64();
+        if elapsed >= ((self.data.report_count + 1) as f64) * report_interval {
+            // Skip the reports which are already late
+            self.data.report_count = (elapsed / report_interval) as usize;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Close the current interval if it is at least `minimum_length` seconds long
+    fn close_interval(&mut self, minimum_length: f64) -> Option<TestInterval> {
+        let sample = Sample {
+            elapsed: self.data.elapsed().as_secs_f64(),
+            total_transfer: self.data.total_transfer,
+            total_packets: self.data.total_packets,
+        };
+
+        let length = sample.elapsed - self.interval_start.elapsed;
+        if length <= 0.0 || length < minimum_length {
+            return None;
+        }
+
+        let bytes = sample.total_transfer - self.interval_start.total_transfer;
+        let packets = sample.total_packets - self.interval_start.total_packets;
+        let interval = TestInterval {
+            start: self.interval_start.elapsed,
+            end: sample.elapsed,
+            bytes,
+            packets,
+            bits_per_second: (bytes * 8) as f64 / length,
+            packets_per_second: packets as f64 / length,
+        };
+        self.interval_start = sample;
+
+        if !self.data.omitted {
+            self.bits_per_second.push(interval.bits_per_second);
+            self.packets_per_second.push(interval.packets_per_second);
+        }
+
+        Some(interval)
     }
 }
 
 pub struct Test {
-    pub data: TestData,
+    pub plan: TestPlan,
     pub options: TestOptions,
+    /// None if interval reports are disabled
+    report_interval: Option<f64>,
+
+    state: Arc<Mutex<TestState>>,
+    counters: Arc<Counters>,
+    /// When the omitted period ends, None before the test starts or without one
+    omit_end: Option<Instant>,
+    /// Cached once the omitted period is over, so that the checks of every packet do not lock
+    /// the state
+    counting: Cell<Option<Counting>>,
+    /// Started and not finished yet
+    running: bool,
+    /// Dropping the sender stops the reporter thread
+    reporter: Option<(Sender<()>, JoinHandle<()>)>,
+    aborted: Arc<AtomicBool>,
+}
+
+/// Lets other threads watch a running test and abort it
+#[derive(Clone)]
+pub struct TestHandle {
+    state: Arc<Mutex<TestState>>,
+    aborted: Arc<AtomicBool>,
+}
+
+impl TestHandle {
+    pub fn data(&self) -> TestData {
+        let mut state = self.state.lock().unwrap();
+        state.sync();
+        state.data.clone()
+    }
+
+    /// Bits per second of the last interval, or the average so far before the first one
+    pub fn bits_per_second(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        state.sync();
+        match state.bits_per_second.last() {
+            Some(&bits_per_second) => bits_per_second,
+            None => {
+                let elapsed = state.data.elapsed().as_secs_f64();
+                if elapsed > 0.0 {
+                    (state.data.total_transfer * 8) as f64 / elapsed
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// The test stops at its next check
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+    }
 }
 
 pub trait TestListener {
     fn on_start(&mut self, data: &TestData);
-    fn on_finish(&mut self, data: &TestData);
-    fn on_report(&mut self, data: &TestData);
+    fn on_finish(&mut self, data: &TestData, summary: &TestSummary);
+    fn on_report(&mut self, data: &TestData, interval: &TestInterval);
+    /// Called when a test could not be run
+    fn on_error(&mut self, _error: &str) {}
+}
+
+/// Lets the owner read what a listener collected after handing it to the tests
+impl<L: TestListener> TestListener for Arc<Mutex<L>> {
+    fn on_start(&mut self, data: &TestData) {
+        self.lock().unwrap().on_start(data);
+    }
+
+    fn on_finish(&mut self, data: &TestData, summary: &TestSummary) {
+        self.lock().unwrap().on_finish(data, summary);
+    }
+
+    fn on_report(&mut self, data: &TestData, interval: &TestInterval) {
+        self.lock().unwrap().on_report(data, interval);
+    }
+
+    fn on_error(&mut self, error: &str) {
+        self.lock().unwrap().on_error(error);
+    }
+}
+
+/// What a `TestListener` is told, for receiving it through a channel
+#[derive(Clone, Debug)]
+pub enum TestEvent {
+    Start(TestData),
+    Report(TestData, TestInterval),
+    Finish(TestData, TestSummary),
+    Error(String),
+}
+
+/// Sends every event, which are dropped once the receiver is gone
+impl TestListener for Sender<TestEvent> {
+    fn on_start(&mut self, data: &TestData) {
+        _ = self.send(TestEvent::Start(data.clone()));
+    }
+
+    fn on_finish(&mut self, data: &TestData, summary: &TestSummary) {
+        _ = self.send(TestEvent::Finish(data.clone(), summary.clone()));
+    }
+
+    fn on_report(&mut self, data: &TestData, interval: &TestInterval) {
+        _ = self.send(TestEvent::Report(data.clone(), interval.clone()));
+    }
+
+    fn on_error(&mut self, error: &str) {
+        _ = self.send(TestEvent::Error(error.to_string()));
+    }
 }
 
 impl Test {
     pub fn new(data: TestData, options: TestOptions) -> Self {
-        Self { data, options }
+        let report_interval = match data.plan.report_interval.unwrap_or(options.report_interval) {
+            interval if interval > 0.0 => Some(interval.max(MIN_REPORT_INTERVAL)),
+            _ => None,
+        };
+        let counters = Arc::new(Counters::default());
+        Self {
+            plan: data.plan.clone(),
+            options,
+            report_interval,
+            state: Arc::new(Mutex::new(TestState {
+                data,
+                counters: counters.clone(),
+                omitted_transfer: 0,
+                omitted_packets: 0,
+                interval_start: Sample::ZERO,
+                bits_per_second: Vec::new(),
+                packets_per_second: Vec::new(),
+            })),
+            counters,
+            omit_end: None,
+            counting: Cell::new(None),
+            running: false,
+            reporter: None,
+            aborted: Arc::new(AtomicBool::new(false)),
+        }
     }
 
-    pub fn elapsed(&self) -> Duration {
-        self.data.elapsed()
+    pub fn handle(&self) -> TestHandle {
+        TestHandle {
+            state: self.state.clone(),
+            aborted: self.aborted.clone(),
+        }
     }
 
-    pub fn transferred(&mut self, n: usize) {
-        self.data.total_transfer += n;
-        self.data.total_packets += 1;
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
 
-        if self.should_report() {
-            self.options
-                .event_handler
-                .borrow_mut()
-                .on_report(&mut self.data);
-        }
+    pub fn transferred(&mut self, n: usize) {
+        // Ends the omitted period on time even without a reporter thread
+        self.counting();
+        self.counters.transfer.fetch_add(n, Ordering::Relaxed);
+        self.counters.packets.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn start(&mut self) {
-        self.data.start_time = Utc::now();
-        self.options.event_handler.borrow_mut().on_start(&self.data);
+        self.running = true;
+        let now = Instant::now();
+        if self.plan.omit > 0.0 {
+            self.omit_end = Duration::try_from_secs_f64(self.plan.omit)
+                .ok()
+                .and_then(|omit| now.checked_add(omit));
+        } else {
+            self.counting.set(Some(Counting {
+                since: now,
+                omitted_transfer: 0,
+                omitted_packets: 0,
+            }));
+        }
+        let data = {
+            let mut state = self.state.lock().unwrap();
+            state.data.start_time = now;
+            state.data.omitted = self.plan.omit > 0.0;
+            state.data.clone()
+        };
+        self.options.notify(|listener| listener.on_start(&data));
+
+        if let Some(report_interval) = self.report_interval {
+            let (stop, stopped) = channel();
+            let state = self.state.clone();
+            let options = self.options.clone();
+            let handle = thread::spawn(move || {
+                report_periodically(state, options, report_interval, stopped)
+            });
+            self.reporter = Some((stop, handle));
+        }
     }
 
     pub fn finish(&mut self) {
-        self.options
-            .event_handler
-            .borrow_mut()
-            .on_finish(&self.data);
+        self.finish_with(None);
     }
 
-    pub fn should_report(&mut self) -> bool {
-        let elapsed = self.elapsed();
-        if elapsed.as_secs_f64() >= (self.data.report_count as f64) * self.options.report_interval {
-            self.data.report_count += 1;
-            true
+    /// Finish the test with an error result
+    pub fn fail(&mut self, error: String) {
+        self.finish_with(Some(error));
+    }
+
+    fn finish_with(&mut self, error: Option<String>) {
+        self.running = false;
+        if let Some((stop, handle)) = self.reporter.take() {
+            drop(stop);
+            _ = handle.join();
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.update_omission();
+        let (elapsed, interval) = if state.data.omitted {
+            (0.0, None)
         } else {
-            false
+            match self.report_interval {
+                Some(report_interval) => {
+                    let interval =
+                        state.close_interval(MINIMUM_TRAILING_INTERVAL * report_interval);
+                    (state.data.elapsed().as_secs_f64(), interval)
+                }
+                None => {
+                    // The whole test is one interval, which only feeds the statistics
+                    state.close_interval(0.0);
+                    (state.data.elapsed().as_secs_f64(), None)
+                }
+            }
+        };
+        let summary = TestSummary {
+            elapsed,
+            bits_per_second: Statistics::new(&state.bits_per_second),
+            packets_per_second: Statistics::new(&state.packets_per_second),
+            error,
+        };
+
+        self.options.notify(|listener| {
+            if let Some(interval) = &interval {
+                listener.on_report(&state.data, interval);
+            }
+            listener.on_finish(&state.data, &summary);
+        });
+    }
+
+    /// Counting after the omitted period, None while still in it
+    fn counting(&self) -> Option<Counting> {
+        if self.counting.get().is_none() && self.omit_end.is_some_and(|end| Instant::now() >= end) {
+            let mut state = self.state.lock().unwrap();
+            state.update_omission();
+            self.counting.set(Some(Counting {
+                since: state.data.start_time,
+                omitted_transfer: state.omitted_transfer,
+                omitted_packets: state.omitted_packets,
+            }));
+        }
+        self.counting.get()
+    }
+
+    /// Whether the stop condition of the plan is reached
+    pub fn is_finished(&self) -> bool {
+        let Some(counting) = self.counting() else {
+            return false;
+        };
+
+        let plan = &self.plan;
+        let elapsed = counting.since.elapsed().as_secs_f64();
+        plan.time_allowed()
+            .is_some_and(|time_allowed| elapsed > time_allowed)
+            || plan
+                .bytes
+                .is_some_and(|bytes| counting.total_transfer(&self.counters) >= bytes)
+            || plan
+                .packets
+                .is_some_and(|packets| counting.total_packets(&self.counters) >= packets)
+    }
+
+    /// Byte size of the next packet, which is truncated to not exceed `bytes` of the plan
+    pub fn next_packet_size(&self) -> usize {
+        match (self.plan.bytes, self.counting()) {
+            (Some(bytes), Some(counting)) => self
+                .plan
+                .packet_size
+                .min(bytes.saturating_sub(counting.total_transfer(&self.counters))),
+            _ => self.plan.packet_size,
+        }
+    }
+}
+
+/// Listeners are told about every started test, even one which ends by an unexpected error
+impl Drop for Test {
+    fn drop(&mut self) {
+        if self.running {
+            self.fail("the test ended without finishing".to_string());
+        }
+    }
+}
+
+/// Reports on time whether or not anything is transferred, until `stopped` is disconnected
+fn report_periodically(
+    state: Arc<Mutex<TestState>>,
+    options: TestOptions,
+    report_interval: f64,
+    stopped: Receiver<()>,
+) {
+    loop {
+        let timeout = state.lock().unwrap().until_next_event(report_interval);
+        let timeout = Duration::try_from_secs_f64(timeout.max(0.0)).unwrap_or(Duration::MAX);
+        match stopped.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+
+        let report = {
+            let mut state = state.lock().unwrap();
+            state.update_omission();
+            if state.should_report(report_interval) {
+                state
+                    .close_interval(0.0)
+                    .map(|interval| (state.data.clone(), interval))
+            } else {
+                None
+            }
+        };
+
+        if let Some((data, interval)) = report {
+            options.notify(|listener| listener.on_report(&data, &interval));
         }
     }
 }
 
-struct EmptyTestEventHandler;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics_of_nothing_are_zero() {
+        let statistics = Statistics::new(&[]);
+        assert_eq!(statistics.average, 0.0);
+        assert_eq!(statistics.standard_deviation, 0.0);
+    }
+
+    #[test]
+    fn statistics_of_odd_count() {
+        let statistics = Statistics::new(&[5.0, 1.0, 3.0]);
+        assert_eq!(statistics.average, 3.0);
+        assert_eq!(statistics.min, 1.0);
+        assert_eq!(statistics.max, 5.0);
+        assert_eq!(statistics.median, 3.0);
+        assert!((statistics.standard_deviation - (8.0f64 / 3.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn statistics_of_even_count() {
+        let statistics = Statistics::new(&[4.0, 2.0, 8.0, 6.0]);
+        assert_eq!(statistics.average, 5.0);
+        assert_eq!(statistics.median, 5.0);
+        assert!((statistics.standard_deviation - 5.0f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn statistics_of_constant_values() {
+        let statistics = Statistics::new(&[7.0; 5]);
+        assert_eq!(statistics.median, 7.0);
+        assert_eq!(statistics.standard_deviation, 0.0);
+    }
+
+    #[test]
+    fn omission_ends_without_reports() {
+        let mut plan = TestPlan::new(1000);
+        plan.omit = 0.05;
+        let (sender, events) = std::sync::mpsc::channel();
+        let mut options = TestOptions::new(0.0);
+        options.add_listener(Box::new(sender));
+        let data = TestData::new(1, plan, TransportMode::Send, "tcp", None);
+        let mut test = Test::new(data, options);
 
-impl TestListener for EmptyTestEventHandler {
-    fn on_start(&mut self, _: &TestData) {}
-    fn on_finish(&mut self, _: &TestData) {}
-    fn on_report(&mut self, _: &TestData) {}
+        test.start();
+        test.transferred(1000);
+        thread::sleep(Duration::from_millis(100));
+        for _ in 0..3 {
+            test.transferred(1000);
+        }
+        thread::sleep(Duration::from_millis(10));
+        test.finish();
+
+        let (data, summary) = events
+            .try_iter()
+            .find_map(|event| match event {
+                TestEvent::Finish(data, summary) => Some((data, summary)),
+                _ => None,
+            })
+            .unwrap();
+        assert!(!data.omitted);
+        assert_eq!(data.total_transfer, 3000);
+        assert_eq!(data.total_packets, 3);
+        assert!(summary.elapsed > 0.0);
+        assert!(summary.bits_per_second.average > 0.0);
+    }
+
+    #[test]
+    fn time_allowed_follows_the_stop_condition() {
+        let mut plan = TestPlan::new(1000);
+        assert_eq!(plan.time_allowed(), Some(plan.duration));
+        plan.bytes = Some(1000);
+        assert_eq!(plan.time_allowed(), None);
+        plan.time_limit = Some(3.0);
+        assert_eq!(plan.time_allowed(), Some(3.0));
+    }
 }
@@ -1,14 +1,22 @@
+mod csv;
+mod influx;
 mod json;
+mod output;
 mod pretty;
+mod statsd;
 
+pub use csv::*;
+pub use influx::*;
 pub use json::*;
+pub use output::*;
 pub use pretty::*;
+pub use statsd::*;
 
-use crate::test::{TestData, TestListener};
+use crate::test::{TestData, TestInterval, TestListener, TestSummary};
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub enum EventType {
     Start,
     Finish,
@@ -18,64 +26,93 @@ pub enum EventType {
 #[derive(Serialize)]
 pub struct Event<'a> {
     r#type: EventType,
+    /// Cumulative totals
     data: &'a TestData,
-
-    #[serde(skip_serializing)]
-    previous_data: &'a Option<TestData>,
-    #[serde(skip_serializing)]
+    /// Seconds elapsed since the test started
+    elapsed: f64,
+    /// Only present on report events
+    interval: Option<&'a TestInterval>,
+    /// Only present on the finish event
+    summary: Option<&'a TestSummary>,
+    /// Wall clock time, never used to measure durations
     timestamp: DateTime<Utc>,
 }
 
 impl<'a> Event<'a> {
-    pub fn new(r#type: EventType, data: &'a TestData, previous_data: &'a Option<TestData>) -> Self {
+    pub fn new(
+        r#type: EventType,
+        data: &'a TestData,
+        interval: Option<&'a TestInterval>,
+        summary: Option<&'a TestSummary>,
+    ) -> Self {
         Self {
             r#type,
             timestamp: Utc::now(),
+            elapsed: data.elapsed().as_secs_f64(),
             data,
-            previous_data,
+            interval,
+            summary,
         }
     }
 }
 
 pub trait Format {
-    fn format(&self, event: &Event) -> String;
+    /// Printed once before the first event
+    fn header(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns `None` if the event is not shown in this format
+    fn format(&self, event: &Event) -> Option<String>;
 }
 
-#[derive(Clone)]
 pub struct FormattedTestPrinter<F: Format> {
     format: F,
-    last_data: Option<TestData>,
+    /// The header is printed once for all tests
+    header_printed: bool,
+    output: Output,
 }
 
 impl<F: Format> FormattedTestPrinter<F> {
-    pub fn new(format: F) -> Self {
+    pub fn new(format: F, output: Output) -> Self {
         Self {
             format,
-            last_data: None,
+            header_printed: false,
+            output,
         }
     }
 }
 
 impl<F: Format> TestListener for FormattedTestPrinter<F> {
     fn on_start(&mut self, data: &TestData) {
-        self.format_and_print(EventType::Start, &data);
+        self.format_and_print(Event::new(EventType::Start, data, None, None));
     }
 
-    fn on_finish(&mut self, data: &TestData) {
-        self.format_and_print(EventType::Finish, &data);
+    fn on_finish(&mut self, data: &TestData, summary: &TestSummary) {
+        self.format_and_print(Event::new(EventType::Finish, data, None, Some(summary)));
     }
 
-    fn on_report(&mut self, data: &TestData) {
-        self.format_and_print(EventType::Report, &data);
+    fn on_report(&mut self, data: &TestData, interval: &TestInterval) {
+        self.format_and_print(Event::new(EventType::Report, data, Some(interval), None));
     }
 }
 
 impl<F: Format> FormattedTestPrinter<F> {
-    fn format_and_print(&mut self, r#type: EventType, data: &TestData) {
-        let formatted = self
-            .format
-            .format(&Event::new(r#type, &data, &self.last_data));
-        println!("{}", formatted);
-        self.last_data = Some(data.clone());
+    fn format_and_print(&mut self, event: Event) {
+        if !self.header_printed {
+            self.header_printed = true;
+            if let Some(header) = self.format.header() {
+                self.write_line(&header);
+            }
+        }
+        if let Some(formatted) = self.format.format(&event) {
+            self.write_line(&formatted);
+        }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if let Err(e) = self.output.write_line(line) {
+            eprintln!("output error: {}", e);
+        }
     }
 }
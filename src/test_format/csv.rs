@@ -0,0 +1,85 @@
+use crate::test_format::{Event, EventType, Format};
+
+/// One row per report and finish event
+#[derive(Clone)]
+pub struct Csv;
+
+const COLUMNS: &[&str] = &[
+    "test_id",
+    "timestamp",
+    "type",
+    "omitted",
+    "interval_start",
+    "interval_end",
+    "bytes",
+    "bits_per_second",
+    "packets",
+    "packets_per_second",
+    "error",
+];
+
+impl Format for Csv {
+    fn header(&self) -> Option<String> {
+        Some(COLUMNS.join(","))
+    }
+
+    fn format(&self, event: &Event) -> Option<String> {
+        let (r#type, start, end, bytes, packets, error) = match event.r#type {
+            EventType::Start => return None,
+            EventType::Report => {
+                let interval = event.interval?;
+                (
+                    "report",
+                    interval.start,
+                    interval.end,
+                    interval.bytes,
+                    interval.packets,
+                    None,
+                )
+            }
+            EventType::Finish => {
+                let summary = event.summary?;
+                (
+                    "finish",
+                    0.0,
+                    summary.elapsed,
+                    event.data.total_transfer,
+                    event.data.total_packets,
+                    summary.error.as_deref(),
+                )
+            }
+        };
+
+        let length = end - start;
+        let (bits_per_second, packets_per_second) = if length > 0.0 {
+            ((bytes * 8) as f64 / length, packets as f64 / length)
+        } else {
+            (0.0, 0.0)
+        };
+
+        Some(format!(
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            event.data.id,
+            event.timestamp.to_rfc3339(),
+            r#type,
+            event.data.omitted,
+            start,
+            end,
+            bytes,
+            bits_per_second,
+            packets,
+            packets_per_second,
+            self.escape(error.unwrap_or("")),
+        ))
+    }
+}
+
+impl Csv {
+    fn escape(&self, value: &str) -> String {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::c::hostname;
+use crate::test::Statistics;
+use crate::test_format::{Event, EventType, Format};
+
+/// InfluxDB line protocol, one line per report and finish event
+#[derive(Clone)]
+pub struct Influx {
+    host: String,
+}
+
+impl Default for Influx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Influx {
+    pub fn new() -> Self {
+        Self {
+            host: hostname().unwrap_or_default(),
+        }
+    }
+
+    /// Tag values can not contain unescaped commas, equal signs and spaces
+    fn escape_tag(&self, value: &str) -> String {
+        value
+            .replace(',', "\\,")
+            .replace('=', "\\=")
+            .replace(' ', "\\ ")
+    }
+
+    fn statistics_fields(&self, name: &str, statistics: &Statistics) -> String {
+        format!(
+            "{name}_average={},{name}_min={},{name}_max={},{name}_median={},{name}_standard_deviation={}",
+            statistics.average,
+            statistics.min,
+            statistics.max,
+            statistics.median,
+            statistics.standard_deviation,
+        )
+    }
+}
+
+impl Format for Influx {
+    fn format(&self, event: &Event) -> Option<String> {
+        let tags = format!(
+            "test_id={},transport={},mode={},host={}",
+            event.data.id,
+            self.escape_tag(&event.data.transport),
+            event.data.mode,
+            self.escape_tag(&self.host),
+        );
+        let timestamp = event.timestamp.timestamp() * 1_000_000_000
+            + event.timestamp.timestamp_subsec_nanos() as i64;
+
+        match event.r#type {
+            EventType::Start => None,
+            EventType::Report => {
+                let interval = event.interval?;
+                Some(format!(
+                    "perf_interval,{} start={},end={},bytes={}i,packets={}i,bits_per_second={},packets_per_second={},omitted={} {}",
+                    tags,
+                    interval.start,
+                    interval.end,
+                    interval.bytes,
+                    interval.packets,
+                    interval.bits_per_second,
+                    interval.packets_per_second,
+                    event.data.omitted,
+                    timestamp,
+                ))
+            }
+            EventType::Finish => {
+                let summary = event.summary?;
+                let mut fields = format!(
+                    "elapsed={},bytes={}i,packets={}i,{},{}",
+                    summary.elapsed,
+                    event.data.total_transfer,
+                    event.data.total_packets,
+                    self.statistics_fields("bits_per_second", &summary.bits_per_second),
+                    self.statistics_fields("packets_per_second", &summary.packets_per_second),
+                );
+                if let Some(error) = &summary.error {
+                    let error = error.replace('\\', "\\\\").replace('"', "\\\"");
+                    fields.push_str(&format!(",error=\"{}\"", error));
+                }
+                Some(format!("perf_summary,{} {} {}", tags, fields, timestamp))
+            }
+        }
+    }
+}
@@ -4,7 +4,7 @@ use crate::test_format::{Event, Format};
 pub struct Json;
 
 impl Format for Json {
-    fn format(&self, event: &Event) -> String {
-        serde_json::to_string(event).unwrap_or("".to_string())
+    fn format(&self, event: &Event) -> Option<String> {
+        serde_json::to_string(event).ok()
     }
 }
@@ -0,0 +1,63 @@
+use std::fs::File;
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+
+/// Where formatted events are written
+pub enum Output {
+    Stdout,
+    File(File),
+    /// Each event is sent as one datagram
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Output {
+    /// Open `-` for stdout, a collector URL or a file path
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        if path == "-" {
+            Ok(Output::Stdout)
+        } else if path.contains("://") {
+            Self::connect(path)
+        } else {
+            Ok(Output::File(File::create(path)?))
+        }
+    }
+
+    /// Connect to a collector at `udp://host:port` or `tcp://host:port`
+    pub fn connect(url: &str) -> std::io::Result<Self> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid collector address \"{}\"", url),
+            )
+        };
+        let (scheme, address) = url.split_once("://").ok_or_else(invalid)?;
+        match scheme {
+            "udp" => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(address)?;
+                Ok(Output::Udp(socket))
+            }
+            "tcp" => Ok(Output::Tcp(TcpStream::connect(address)?)),
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        match self {
+            Output::Stdout => {
+                println!("{}", line);
+                Ok(())
+            }
+            Output::File(file) => {
+                file.write_all(line.as_bytes())?;
+                file.write_all(b"\n")
+            }
+            Output::Udp(socket) => socket.send(line.as_bytes()).map(|_| ()),
+            Output::Tcp(stream) => {
+                stream.write_all(line.as_bytes())?;
+                stream.write_all(b"\n")
+            }
+        }
+    }
+}
@@ -1,50 +1,111 @@
+use crate::test::Statistics;
 use crate::test_format::{Event, EventType, Format};
 
 #[derive(Clone)]
 pub struct Pretty;
 
 impl Format for Pretty {
-    fn format(&self, event: &Event) -> String {
-        match event.r#type {
+    fn format(&self, event: &Event) -> Option<String> {
+        let formatted = match event.r#type {
             EventType::Start => {
-                format!("Test started #{}", event.data.id)
-            }
-            EventType::Finish => {
-                format!("Test finished #{}", event.data.id)
-            }
-            EventType::Report => {
-                let total_transfer = self.format_bytes(event.data.total_transfer);
-                if let Some(previous_data) = event.previous_data {
-                    let throughput = self.format_bytes(
-                        (event.data.total_transfer - previous_data.total_transfer) * 8,
-                    );
+                if event.data.omitted {
                     format!(
-                        "[{:.2}s] {}B ({}bit/s, {} packets)",
-                        event.data.elapsed().as_secs_f64(),
-                        total_transfer,
-                        throughput,
-                        event.data.total_packets,
+                        "Test started #{} (omitting first {:.2}s)",
+                        event.data.id, event.data.plan.omit
                     )
                 } else {
+                    format!("Test started #{}", event.data.id)
+                }
+            }
+            EventType::Finish => {
+                let finished = format!(
+                    "Test finished #{} in {:.2}s ({}B, {} packets)",
+                    event.data.id,
+                    event.elapsed,
+                    self.format_bytes(event.data.total_transfer),
+                    event.data.total_packets,
+                );
+                let finished = match event.summary.and_then(|summary| summary.error.as_ref()) {
+                    Some(error) => {
+                        format!("{}\nTest failed #{}: {}", finished, event.data.id, error)
+                    }
+                    None => finished,
+                };
+                if let Some(summary) = event.summary {
                     format!(
-                        "[{:.2}s] {}B ({} packets)",
-                        event.data.elapsed().as_secs_f64(),
-                        total_transfer,
-                        event.data.total_packets
+                        "{}\n[0.00-{:.2}s] {}\n[0.00-{:.2}s] {}",
+                        finished,
+                        summary.elapsed,
+                        format_statistics(&summary.bits_per_second, "bit/s"),
+                        summary.elapsed,
+                        format_statistics(&summary.packets_per_second, "packets/s"),
                     )
+                } else {
+                    finished
                 }
             }
-        }
+            EventType::Report => {
+                let omitted = if event.data.omitted { " (omitted)" } else { "" };
+                match event.interval {
+                    Some(interval) => format!(
+                        "[{:.2}-{:.2}s] {}B ({}bit/s, {} packets, {}packets/s){}",
+                        interval.start,
+                        interval.end,
+                        self.format_bytes(interval.bytes),
+                        format_number(interval.bits_per_second),
+                        interval.packets,
+                        format_number(interval.packets_per_second),
+                        omitted,
+                    ),
+                    None => format!(
+                        "[{:.2}s] {}B ({} packets){}",
+                        event.elapsed,
+                        self.format_bytes(event.data.total_transfer),
+                        event.data.total_packets,
+                        omitted,
+                    ),
+                }
+            }
+        };
+        Some(formatted)
     }
 }
 
 impl Pretty {
     fn format_bytes(&self, n: usize) -> String {
-        let formats = ["", "K", "M", "G", "T", "P"];
-        let base = 1000_f64;
-        let n = n as f64;
-        let index = (n.log10() / base.log10()).floor() as usize;
-        let n = n / base.powi(index as i32);
-        format!("{:.2}{}", n, formats[index])
+        format_number(n as f64)
+    }
+}
+
+/// Such as "9.41G", with the unit left to the caller
+pub fn format_number(n: f64) -> String {
+    let formats = ["", "K", "M", "G", "T", "P"];
+    let base = 1000_f64;
+    // Larger numbers keep the largest suffix
+    let index = ((n.log10() / base.log10()).floor() as usize).min(formats.len() - 1);
+    let n = n / base.powi(index as i32);
+    format!("{:.2}{}", n, formats[index])
+}
+
+/// One line with every statistic of a summary
+pub fn format_statistics(statistics: &Statistics, unit: &str) -> String {
+    format!(
+        "avg {}{unit}, min {}{unit}, max {}{unit}, median {}{unit}, stddev {}{unit}",
+        format_number(statistics.average),
+        format_number(statistics.min),
+        format_number(statistics.max),
+        format_number(statistics.median),
+        format_number(statistics.standard_deviation),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_beyond_the_largest_suffix_keep_it() {
+        assert_eq!(format_number(9.41e9), "9.41G");
+        assert_eq!(format_number(2e18), "2000.00P");
     }
 }
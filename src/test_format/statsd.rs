@@ -0,0 +1,79 @@
+use crate::c::hostname;
+use crate::test_format::{Event, EventType, Format};
+
+/// StatsD gauges and counters named `perf.<host>.<transport>.<mode>.<metric>`
+#[derive(Clone)]
+pub struct Statsd {
+    host: String,
+}
+
+impl Default for Statsd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Statsd {
+    pub fn new() -> Self {
+        Self {
+            host: hostname().unwrap_or_default(),
+        }
+    }
+
+    /// Dots separate the name segments and colons the value
+    fn sanitize(&self, segment: &str) -> String {
+        segment.replace(['.', ':', '|', '@'], "_")
+    }
+}
+
+impl Format for Statsd {
+    fn format(&self, event: &Event) -> Option<String> {
+        let prefix = format!(
+            "perf.{}.{}.{}",
+            self.sanitize(&self.host),
+            self.sanitize(&event.data.transport),
+            event.data.mode,
+        );
+
+        match event.r#type {
+            EventType::Start => Some(format!("{}.tests_started:1|c", prefix)),
+            EventType::Report => {
+                let interval = event.interval?;
+                if event.data.omitted {
+                    return None;
+                }
+                Some(
+                    [
+                        format!("{}.bytes:{}|c", prefix, interval.bytes),
+                        format!("{}.packets:{}|c", prefix, interval.packets),
+                        format!("{}.bits_per_second:{}|g", prefix, interval.bits_per_second),
+                        format!(
+                            "{}.packets_per_second:{}|g",
+                            prefix, interval.packets_per_second
+                        ),
+                    ]
+                    .join("\n"),
+                )
+            }
+            EventType::Finish => {
+                let summary = event.summary?;
+                let result = if summary.error.is_some() {
+                    "tests_failed"
+                } else {
+                    "tests_finished"
+                };
+                Some(
+                    [
+                        format!("{}.{}:1|c", prefix, result),
+                        format!("{}.elapsed:{}|g", prefix, summary.elapsed),
+                        format!(
+                            "{}.average_bits_per_second:{}|g",
+                            prefix, summary.bits_per_second.average
+                        ),
+                    ]
+                    .join("\n"),
+                )
+            }
+        }
+    }
+}
@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 use std::io::{Read, Write};
+use std::net::SocketAddrV4;
 
 pub type Result<T> = std::io::Result<T>;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum TransportMode {
     #[serde(rename = "send")]
     Send,
@@ -11,12 +13,36 @@ pub enum TransportMode {
     Receive,
 }
 
+impl TransportMode {
+    /// Mode of the other side
+    pub fn opposite(&self) -> Self {
+        match self {
+            TransportMode::Send => TransportMode::Receive,
+            TransportMode::Receive => TransportMode::Send,
+        }
+    }
+}
+
+impl Display for TransportMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportMode::Send => f.write_str("send"),
+            TransportMode::Receive => f.write_str("receive"),
+        }
+    }
+}
+
 pub trait Server<L: Listener<Conn>, Conn: Connection> {
     fn listen(&self) -> Result<L>;
 }
 
 pub trait Listener<Conn: Connection> {
-    fn accept(&self) -> Result<Conn>;
+    /// The next connection and the address of its client
+    fn accept(&self) -> Result<(Conn, SocketAddrV4)>;
+    /// Address clients connect to, with the port chosen by the system if 0 was configured
+    fn local_address(&self) -> Result<SocketAddrV4>;
+    /// Make `accept` fail with `WouldBlock` after waiting this long
+    fn set_accept_timeout(&self, milliseconds: Option<u64>) -> Result<()>;
 }
 
 pub trait Client<Conn: Connection> {
@@ -27,6 +53,27 @@ pub trait SetReadTimeout {
     fn set_read_timeout(&mut self, milliseconds: Option<u64>) -> std::io::Result<()>;
 }
 
+/// Largest packet of a stream transport, so that a plan cannot make the peer allocate any size
+pub const MAX_STREAM_PACKET_SIZE: usize = 4 * 1024 * 1024;
+/// Largest packet of a datagram transport, above what a datagram can carry
+pub const MAX_DATAGRAM_PACKET_SIZE: usize = 64 * 1024;
+
 pub trait Connection: Read + Write + Clone + Send + SetReadTimeout {
     fn header_size() -> usize;
+    /// Largest `packet_size` a test plan may ask for
+    fn max_packet_size() -> usize {
+        MAX_STREAM_PACKET_SIZE
+    }
+    /// Name of the transport actually used, such as "tcp"
+    fn transport(&self) -> &'static str;
+    fn peer_address(&self) -> Result<SocketAddrV4>;
+    /// Make blocked writes fail with `WouldBlock` after this long. Datagrams are sent without
+    /// waiting for the peer, so only stream transports need it
+    fn set_write_timeout(&mut self, _milliseconds: Option<u64>) -> Result<()> {
+        Ok(())
+    }
+    /// Called after the handshake, before the test data is transferred
+    fn start_transfer(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
@@ -1,11 +1,14 @@
+mod ktls;
 mod sockets;
 
 mod raw;
 mod tcp;
+mod tls;
 mod udp;
+#[allow(dead_code)]
 mod zero_copy;
 
 pub use raw::*;
 pub use tcp::*;
+pub use tls::*;
 pub use udp::*;
-pub use zero_copy::*;
@@ -0,0 +1,220 @@
+use crate::c::handle_os_result;
+use libc::*;
+use rustls::crypto::cipher::NONCE_LEN;
+use rustls::{CipherSuite, ConnectionTrafficSecrets, ExtractedSecrets, ProtocolVersion};
+
+// From linux/tls.h
+const TLS_TX: c_int = 1;
+const TLS_RX: c_int = 2;
+const TLS_GET_RECORD_TYPE: c_int = 2;
+const TLS_1_2_VERSION: u16 = 0x0303;
+const TLS_1_3_VERSION: u16 = 0x0304;
+const TLS_CIPHER_AES_GCM_128: u16 = 51;
+const TLS_CIPHER_AES_GCM_256: u16 = 52;
+const TLS_CIPHER_CHACHA20_POLY1305: u16 = 54;
+// Content types of TLS records
+const RECORD_ALERT: u8 = 21;
+const RECORD_APPLICATION_DATA: u8 = 23;
+
+#[repr(C)]
+struct CryptoInfo {
+    version: u16,
+    cipher_type: u16,
+}
+
+#[repr(C)]
+struct AesGcm128 {
+    info: CryptoInfo,
+    iv: [u8; 8],
+    key: [u8; 16],
+    salt: [u8; 4],
+    rec_seq: [u8; 8],
+}
+
+#[repr(C)]
+struct AesGcm256 {
+    info: CryptoInfo,
+    iv: [u8; 8],
+    key: [u8; 32],
+    salt: [u8; 4],
+    rec_seq: [u8; 8],
+}
+
+#[repr(C)]
+struct Chacha20Poly1305 {
+    info: CryptoInfo,
+    iv: [u8; 12],
+    key: [u8; 32],
+    rec_seq: [u8; 8],
+}
+
+fn unsupported(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, message)
+}
+
+/// Check that the kernel can take over a session with this version and cipher suite
+pub fn check_supported(version: ProtocolVersion, suite: CipherSuite) -> std::io::Result<()> {
+    match version {
+        ProtocolVersion::TLSv1_2 | ProtocolVersion::TLSv1_3 => {}
+        _ => {
+            return Err(unsupported(format!(
+                "{:?} is not supported by kTLS",
+                version
+            )))
+        }
+    }
+    match suite {
+        CipherSuite::TLS13_AES_128_GCM_SHA256
+        | CipherSuite::TLS13_AES_256_GCM_SHA384
+        | CipherSuite::TLS13_CHACHA20_POLY1305_SHA256
+        | CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256
+        | CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256
+        | CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384
+        | CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384
+        | CipherSuite::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256
+        | CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256 => Ok(()),
+        _ => Err(unsupported(format!("{:?} is not supported by kTLS", suite))),
+    }
+}
+
+/// Attach the TLS upper layer protocol, which fails if the kernel has no TLS support
+pub fn attach(fd: c_int) -> std::io::Result<()> {
+    let name = b"tls";
+    unsafe {
+        handle_os_result(setsockopt(
+            fd,
+            SOL_TCP,
+            TCP_ULP,
+            name.as_ptr() as *const c_void,
+            name.len() as socklen_t,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Hand the keys over to the kernel, after which `send` and `recv` encrypt and decrypt
+pub fn install(
+    fd: c_int,
+    version: ProtocolVersion,
+    secrets: ExtractedSecrets,
+) -> std::io::Result<()> {
+    let version = match version {
+        ProtocolVersion::TLSv1_2 => TLS_1_2_VERSION,
+        _ => TLS_1_3_VERSION,
+    };
+    let (tx_sequence, tx) = secrets.tx;
+    let (rx_sequence, rx) = secrets.rx;
+    set_crypto_info(fd, TLS_TX, version, tx_sequence, tx)?;
+    set_crypto_info(fd, TLS_RX, version, rx_sequence, rx)
+}
+
+fn set_crypto_info(
+    fd: c_int,
+    direction: c_int,
+    version: u16,
+    sequence: u64,
+    secrets: ConnectionTrafficSecrets,
+) -> std::io::Result<()> {
+    let rec_seq = sequence.to_be_bytes();
+    match secrets {
+        ConnectionTrafficSecrets::Aes128Gcm { key, iv } => {
+            let iv: &[u8; NONCE_LEN] = iv.as_ref().try_into().unwrap();
+            let info = AesGcm128 {
+                info: CryptoInfo {
+                    version,
+                    cipher_type: TLS_CIPHER_AES_GCM_128,
+                },
+                iv: iv[4..].try_into().unwrap(),
+                key: key
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| unsupported("invalid key".to_string()))?,
+                salt: iv[..4].try_into().unwrap(),
+                rec_seq,
+            };
+            set_option(fd, direction, &info)
+        }
+        ConnectionTrafficSecrets::Aes256Gcm { key, iv } => {
+            let iv: &[u8; NONCE_LEN] = iv.as_ref().try_into().unwrap();
+            let info = AesGcm256 {
+                info: CryptoInfo {
+                    version,
+                    cipher_type: TLS_CIPHER_AES_GCM_256,
+                },
+                iv: iv[4..].try_into().unwrap(),
+                key: key
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| unsupported("invalid key".to_string()))?,
+                salt: iv[..4].try_into().unwrap(),
+                rec_seq,
+            };
+            set_option(fd, direction, &info)
+        }
+        ConnectionTrafficSecrets::Chacha20Poly1305 { key, iv } => {
+            let info = Chacha20Poly1305 {
+                info: CryptoInfo {
+                    version,
+                    cipher_type: TLS_CIPHER_CHACHA20_POLY1305,
+                },
+                iv: iv.as_ref().try_into().unwrap(),
+                key: key
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| unsupported("invalid key".to_string()))?,
+                rec_seq,
+            };
+            set_option(fd, direction, &info)
+        }
+        _ => Err(unsupported("cipher is not supported by kTLS".to_string())),
+    }
+}
+
+fn set_option<T>(fd: c_int, direction: c_int, info: &T) -> std::io::Result<()> {
+    unsafe {
+        handle_os_result(setsockopt(
+            fd,
+            SOL_TLS,
+            direction,
+            info as *const T as *const c_void,
+            std::mem::size_of::<T>() as socklen_t,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Receive decrypted application data. The kernel hands every other record over on its own with
+/// its type, and fails plain reads with `EIO` on them: an alert, such as the close_notify of a
+/// finished test, ends the stream, and handshake messages, such as session tickets, are skipped
+pub fn receive(fd: c_int, buffer: &mut [u8]) -> std::io::Result<usize> {
+    loop {
+        let mut control = [0u64; 8];
+        let mut iov = iovec {
+            iov_base: buffer.as_mut_ptr() as *mut c_void,
+            iov_len: buffer.len(),
+        };
+        let (received, record_type) = unsafe {
+            let mut message = std::mem::zeroed::<msghdr>();
+            message.msg_iov = &mut iov;
+            message.msg_iovlen = 1;
+            message.msg_control = control.as_mut_ptr() as *mut c_void;
+            message.msg_controllen = std::mem::size_of_val(&control) as _;
+            let received = handle_os_result(recvmsg(fd, &mut message, 0))? as usize;
+            let cmsg = CMSG_FIRSTHDR(&message);
+            let record_type = if cmsg.is_null()
+                || (*cmsg).cmsg_level != SOL_TLS
+                || (*cmsg).cmsg_type != TLS_GET_RECORD_TYPE
+            {
+                RECORD_APPLICATION_DATA
+            } else {
+                *CMSG_DATA(cmsg)
+            };
+            (received, record_type)
+        };
+        match record_type {
+            RECORD_APPLICATION_DATA => return Ok(received),
+            RECORD_ALERT => return Ok(0),
+            _ => {}
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::c::*;
-use crate::transport::{Client, Connection, Server, SetReadTimeout};
+use crate::transport::{Client, Connection, Server, SetReadTimeout, MAX_DATAGRAM_PACKET_SIZE};
 use crate::transports::sockets::{DgramListener, DgramSocket};
 use etherparse::{Ipv4Header, SerializedSize};
 use libc::*;
@@ -65,6 +65,18 @@ impl Connection for RawConnection {
     fn header_size() -> usize {
         Ipv4Header::SERIALIZED_SIZE
     }
+
+    fn max_packet_size() -> usize {
+        MAX_DATAGRAM_PACKET_SIZE
+    }
+
+    fn transport(&self) -> &'static str {
+        "raw"
+    }
+
+    fn peer_address(&self) -> Result<SocketAddrV4> {
+        Ok(self.destination)
+    }
 }
 
 impl Drop for RawConnection {
@@ -133,7 +145,7 @@ impl Client<RawConnection> for RawClient {
             ))?;
             let socket = DgramSocket::new(fd);
             socket.connect(&self.destination)?;
-            Ok(RawConnection::new(socket, self.destination.clone()))
+            Ok(RawConnection::new(socket, self.destination))
         }
     }
 }
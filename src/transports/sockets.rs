@@ -2,4 +2,3 @@ mod dgram;
 mod stream;
 
 pub use dgram::*;
-pub use stream::*;
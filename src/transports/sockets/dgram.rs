@@ -19,21 +19,19 @@ impl DgramSocket {
     }
 
     pub fn recvfrom(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddrV4)> {
-        loop {
-            unsafe {
-                let mut addr: sockaddr_in = std::mem::zeroed();
-                let mut addrlen: socklen_t = std::mem::size_of_val(&addr) as socklen_t;
-                let read = handle_os_result(recvfrom(
-                    self.fd.value(),
-                    buf.as_mut_ptr() as *mut c_void,
-                    buf.len(),
-                    MSG_NOSIGNAL,
-                    &mut addr as *mut sockaddr_in as *mut sockaddr,
-                    &mut addrlen as *mut socklen_t,
-                ))?;
-
-                break Ok((read as usize, SocketAddrV4::from_c(&addr)));
-            }
+        unsafe {
+            let mut addr: sockaddr_in = std::mem::zeroed();
+            let mut addrlen: socklen_t = std::mem::size_of_val(&addr) as socklen_t;
+            let read = handle_os_result(recvfrom(
+                self.fd.value(),
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+                MSG_NOSIGNAL,
+                &mut addr as *mut sockaddr_in as *mut sockaddr,
+                &mut addrlen as *mut socklen_t,
+            ))?;
+
+            Ok((read as usize, SocketAddrV4::from_c(&addr)))
         }
     }
 
@@ -52,7 +50,7 @@ impl DgramSocket {
     }
 
     pub fn connect(&self, destination: &SocketAddrV4) -> std::io::Result<()> {
-        self.sendto(&[], &destination)?;
+        self.sendto(&[], destination)?;
         Ok(())
     }
 
@@ -63,6 +61,10 @@ impl DgramSocket {
     pub fn set_timeout(&self, milliseconds: Option<u64>) -> std::io::Result<()> {
         self.fd.set_timeout(milliseconds)
     }
+
+    pub fn local_address(&self) -> std::io::Result<SocketAddrV4> {
+        self.fd.local_address()
+    }
 }
 
 pub struct DgramListener<Conn: Connection, ConnFactory: ConnectionFactory<Conn>> {
@@ -84,20 +86,31 @@ impl<Conn: Connection, ConnFactory: ConnectionFactory<Conn>> DgramListener<Conn,
 impl<Conn: Connection, ConnFactory: ConnectionFactory<Conn>> Listener<Conn>
     for DgramListener<Conn, ConnFactory>
 {
-    fn accept(&self) -> Result<Conn> {
+    fn accept(&self) -> Result<(Conn, SocketAddrV4)> {
         loop {
             let mut buffer = [0; 1050];
             let (read, address) = self.socket.recvfrom(&mut buffer)?;
 
             let payload = &buffer[Conn::header_size()..read];
 
-            if payload.len() == 0 {
-                break Ok(self
+            if payload.is_empty() {
+                let connection = self
                     .connection_factory
-                    .new_connection(self.socket.clone(), address));
+                    .new_connection(self.socket.clone(), address);
+                break Ok((connection, address));
             }
         }
     }
+
+    fn local_address(&self) -> Result<SocketAddrV4> {
+        self.socket.local_address()
+    }
+
+    /// Connections share the socket of the listener, so the timeout must be set again before
+    /// each `accept`
+    fn set_accept_timeout(&self, milliseconds: Option<u64>) -> Result<()> {
+        self.socket.set_timeout(milliseconds)
+    }
 }
 
 pub trait ConnectionFactory<Conn: Connection> {
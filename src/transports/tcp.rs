@@ -16,6 +16,10 @@ impl TcpConnection {
     fn new(fd: Fd) -> Self {
         Self { fd: Arc::new(fd) }
     }
+
+    pub fn fd(&self) -> i32 {
+        self.fd.value()
+    }
 }
 
 impl Read for TcpConnection {
@@ -58,6 +62,27 @@ impl Connection for TcpConnection {
     fn header_size() -> usize {
         0
     }
+
+    fn transport(&self) -> &'static str {
+        "tcp"
+    }
+
+    fn set_write_timeout(&mut self, milliseconds: Option<u64>) -> Result<()> {
+        self.fd.set_write_timeout(milliseconds)
+    }
+
+    fn peer_address(&self) -> Result<SocketAddrV4> {
+        unsafe {
+            let mut address = std::mem::zeroed::<sockaddr_in>();
+            let mut address_length = std::mem::size_of::<sockaddr_in>() as socklen_t;
+            handle_os_result(getpeername(
+                self.fd.value(),
+                &mut address as *mut sockaddr_in as *mut sockaddr,
+                &mut address_length as *mut socklen_t,
+            ))?;
+            Ok(SocketAddrV4::from_c(&address))
+        }
+    }
 }
 
 pub struct TcpClient {
@@ -97,18 +122,26 @@ impl TcpListener {
 }
 
 impl Listener<TcpConnection> for TcpListener {
-    fn accept(&self) -> Result<TcpConnection> {
+    fn accept(&self) -> Result<(TcpConnection, SocketAddrV4)> {
         unsafe {
             let mut address = std::mem::zeroed::<sockaddr_in>();
-            let mut address_length: socklen_t = 0;
+            let mut address_length = std::mem::size_of::<sockaddr_in>() as socklen_t;
             let fd = Fd::new(handle_os_result(accept(
                 self.fd.value(),
                 &mut address as *mut sockaddr_in as *mut sockaddr,
                 &mut address_length as *mut socklen_t,
             ))?);
-            Ok(TcpConnection::new(fd))
+            Ok((TcpConnection::new(fd), SocketAddrV4::from_c(&address)))
         }
     }
+
+    fn local_address(&self) -> Result<SocketAddrV4> {
+        self.fd.local_address()
+    }
+
+    fn set_accept_timeout(&self, milliseconds: Option<u64>) -> Result<()> {
+        self.fd.set_timeout(milliseconds)
+    }
 }
 
 pub struct TcpServer {
@@ -0,0 +1,480 @@
+use crate::transport::{Client, Connection, Listener, Server, SetReadTimeout};
+use crate::transports::ktls;
+use crate::transports::{TcpClient, TcpConnection, TcpListener, TcpServer};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::io::{BufReader, Read, Write};
+use std::net::SocketAddrV4;
+use std::sync::{Arc, Mutex};
+
+type Result<T> = crate::transport::Result<T>;
+
+fn invalid_input(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+/// Crypto provider limited to the named cipher suites, or with all of them if none is named
+fn provider(cipher_suites: &[String]) -> Result<Arc<CryptoProvider>> {
+    let mut provider = ring::default_provider();
+    if !cipher_suites.is_empty() {
+        let mut selected = Vec::new();
+        for name in cipher_suites {
+            let suite = provider
+                .cipher_suites
+                .iter()
+                .find(|suite| format!("{:?}", suite.suite()) == *name)
+                .ok_or_else(|| invalid_input(format!("unknown cipher suite \"{}\"", name)))?;
+            selected.push(*suite);
+        }
+        provider.cipher_suites = selected;
+    }
+    Ok(Arc::new(provider))
+}
+
+fn tls_error(error: rustls::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
+
+/// Bytes of a TLS record header, the last two of which are the length of the record
+const RECORD_HEADER_SIZE: usize = 5;
+
+/// The socket as seen by the TLS session
+struct TlsIo<'a> {
+    tcp: &'a mut TcpConnection,
+    /// Read no further than the end of the current record, so that no part of the records
+    /// after the handshake is buffered in userspace when the kernel takes over the session
+    by_record: bool,
+    /// Bytes left of the current record
+    remaining: &'a mut usize,
+}
+
+impl Read for TlsIo<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        if !self.by_record {
+            return self.tcp.read(buffer);
+        }
+        if *self.remaining > 0 {
+            let limit = buffer.len().min(*self.remaining);
+            let read = self.tcp.read(&mut buffer[..limit])?;
+            *self.remaining -= read;
+            return Ok(read);
+        }
+
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.tcp.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(0),
+                Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        *self.remaining = u16::from_be_bytes([header[3], header[4]]) as usize;
+        buffer[..header.len()].copy_from_slice(&header);
+        Ok(header.len())
+    }
+}
+
+impl Write for TlsIo<'_> {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        self.tcp.write(buffer)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.tcp.flush()
+    }
+}
+
+/// The TLS session and the socket below it, shared by all clones of a connection
+struct TlsStream {
+    /// None once the kernel took over the session
+    tls: Option<rustls::Connection>,
+    tcp: TcpConnection,
+    /// Whether to switch to kTLS when the transfer starts
+    ktls: bool,
+    /// Bytes left of the record being read while reading record by record
+    record_remaining: usize,
+}
+
+impl TlsStream {
+    /// Send everything encrypted so far
+    fn flush_tls(tls: &mut rustls::Connection, tcp: &mut TcpConnection) -> std::io::Result<()> {
+        while tls.wants_write() {
+            match tls.write_tls(tcp) {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Hand the session over to the kernel. Nothing is changed if the kernel or the negotiated
+    /// cipher suite does not support it, otherwise errors leave the connection unusable
+    fn enable_ktls(&mut self) -> std::io::Result<()> {
+        let Some(tls) = &self.tls else {
+            return Ok(());
+        };
+        let (Some(version), Some(suite)) = (tls.protocol_version(), tls.negotiated_cipher_suite())
+        else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "the handshake is not complete",
+            ));
+        };
+        ktls::check_supported(version, suite.suite())?;
+        ktls::attach(self.tcp.fd())?;
+
+        let tls = self.tls.take().unwrap();
+        let secrets = tls.dangerous_extract_secrets().map_err(tls_error)?;
+        ktls::install(self.tcp.fd(), version, secrets)
+    }
+}
+
+impl Drop for TlsStream {
+    fn drop(&mut self) {
+        // Let the peer tell a finished test from a truncated one
+        if let Some(tls) = &mut self.tls {
+            tls.send_close_notify();
+            _ = TlsStream::flush_tls(tls, &mut self.tcp);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TlsConnection {
+    stream: Arc<Mutex<TlsStream>>,
+    tcp: TcpConnection,
+}
+
+impl TlsConnection {
+    fn new(tls: rustls::Connection, tcp: TcpConnection, ktls: bool) -> Self {
+        Self {
+            stream: Arc::new(Mutex::new(TlsStream {
+                tls: Some(tls),
+                tcp: tcp.clone(),
+                ktls,
+                record_remaining: 0,
+            })),
+            tcp,
+        }
+    }
+}
+
+impl Read for TlsConnection {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let stream = &mut *self.stream.lock().unwrap();
+        let Some(tls) = &mut stream.tls else {
+            // The kernel decrypts
+            return ktls::receive(stream.tcp.fd(), buffer);
+        };
+        loop {
+            match tls.reader().read(buffer) {
+                Ok(read) => return Ok(read),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            // Nothing decrypted is buffered, so receive more records
+            let (read, _) = tls.complete_io(&mut TlsIo {
+                tcp: &mut stream.tcp,
+                by_record: stream.ktls,
+                remaining: &mut stream.record_remaining,
+            })?;
+            if read == 0 {
+                // The peer closed the connection
+                return match tls.reader().read(buffer) {
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(0),
+                    result => result,
+                };
+            }
+        }
+    }
+}
+
+impl Write for TlsConnection {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        let stream = &mut *self.stream.lock().unwrap();
+        let Some(tls) = &mut stream.tls else {
+            // The kernel encrypts
+            return stream.tcp.write(buffer);
+        };
+        if tls.is_handshaking() {
+            tls.complete_io(&mut TlsIo {
+                tcp: &mut stream.tcp,
+                by_record: stream.ktls,
+                remaining: &mut stream.record_remaining,
+            })?;
+        }
+        // Records left over by a write which timed out go first
+        TlsStream::flush_tls(tls, &mut stream.tcp)?;
+        let written = tls.writer().write(buffer)?;
+        match TlsStream::flush_tls(tls, &mut stream.tcp) {
+            // The plaintext is taken, and the next write sends the rest of its records
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(written),
+            result => result.map(|_| written),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let stream = &mut *self.stream.lock().unwrap();
+        match &mut stream.tls {
+            Some(tls) => TlsStream::flush_tls(tls, &mut stream.tcp),
+            None => Ok(()),
+        }
+    }
+}
+
+impl SetReadTimeout for TlsConnection {
+    fn set_read_timeout(&mut self, milliseconds: Option<u64>) -> std::io::Result<()> {
+        self.tcp.set_read_timeout(milliseconds)
+    }
+}
+
+impl Connection for TlsConnection {
+    fn header_size() -> usize {
+        0
+    }
+
+    fn transport(&self) -> &'static str {
+        if self.stream.lock().unwrap().tls.is_none() {
+            "ktls"
+        } else {
+            "tls"
+        }
+    }
+
+    fn set_write_timeout(&mut self, milliseconds: Option<u64>) -> Result<()> {
+        self.tcp.set_write_timeout(milliseconds)
+    }
+
+    fn peer_address(&self) -> Result<SocketAddrV4> {
+        self.tcp.peer_address()
+    }
+
+    fn start_transfer(&mut self) -> Result<()> {
+        let stream = &mut *self.stream.lock().unwrap();
+        if !stream.ktls {
+            return Ok(());
+        }
+        stream.ktls = false;
+        match stream.enable_ktls() {
+            Ok(()) => Ok(()),
+            Err(e) if stream.tls.is_some() => {
+                eprintln!("kTLS not enabled, staying with userspace TLS: {}", e);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Accepts any certificate, since the cost of encryption is measured and not the identity of
+/// the server
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            certificate,
+            signature,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            certificate,
+            signature,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn read_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    rustls_pemfile::certs(&mut reader).collect()
+}
+
+pub struct TlsClient {
+    tcp: TcpClient,
+    server_name: ServerName<'static>,
+    config: Arc<rustls::ClientConfig>,
+    ktls: bool,
+}
+
+impl TlsClient {
+    /// Without `ca_certificate` the certificate of the server is not verified. With `ktls` the
+    /// kernel takes over the session after the handshake if it can
+    pub fn new(
+        address: SocketAddrV4,
+        server_name: &str,
+        ca_certificate: Option<&str>,
+        cipher_suites: &[String],
+        ktls: bool,
+    ) -> Result<Self> {
+        let provider = provider(cipher_suites)?;
+        let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?;
+        let mut config = match ca_certificate {
+            Some(path) => {
+                let mut roots = rustls::RootCertStore::empty();
+                for certificate in read_certificates(path)? {
+                    roots.add(certificate).map_err(tls_error)?;
+                }
+                builder.with_root_certificates(roots)
+            }
+            None => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerification(provider))),
+        }
+        .with_no_client_auth();
+        config.enable_secret_extraction = ktls;
+        let server_name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| invalid_input(format!("invalid server name: {}", e)))?;
+
+        Ok(Self {
+            tcp: TcpClient::new(address),
+            server_name,
+            config: Arc::new(config),
+            ktls,
+        })
+    }
+}
+
+impl Client<TlsConnection> for TlsClient {
+    fn connect(&self) -> Result<TlsConnection> {
+        let tcp = self.tcp.connect()?;
+        let tls = rustls::ClientConnection::new(self.config.clone(), self.server_name.clone())
+            .map_err(tls_error)?;
+        Ok(TlsConnection::new(tls.into(), tcp, self.ktls))
+    }
+}
+
+pub struct TlsListener {
+    tcp: TcpListener,
+    config: Arc<rustls::ServerConfig>,
+    ktls: bool,
+}
+
+impl Listener<TlsConnection> for TlsListener {
+    /// The handshake happens on the first read or write, so that a failing client does not
+    /// stop the listener
+    fn accept(&self) -> Result<(TlsConnection, SocketAddrV4)> {
+        let (tcp, address) = self.tcp.accept()?;
+        let tls = rustls::ServerConnection::new(self.config.clone()).map_err(tls_error)?;
+        Ok((TlsConnection::new(tls.into(), tcp, self.ktls), address))
+    }
+
+    fn local_address(&self) -> Result<SocketAddrV4> {
+        self.tcp.local_address()
+    }
+
+    fn set_accept_timeout(&self, milliseconds: Option<u64>) -> Result<()> {
+        self.tcp.set_accept_timeout(milliseconds)
+    }
+}
+
+pub struct TlsServer {
+    tcp: TcpServer,
+    config: Arc<rustls::ServerConfig>,
+    ktls: bool,
+}
+
+impl TlsServer {
+    /// Without `certificate` and `private_key` a self-signed certificate is generated. With
+    /// `ktls` the kernel takes over the session after the handshake if it can
+    pub fn new(
+        address: SocketAddrV4,
+        certificate: Option<&str>,
+        private_key: Option<&str>,
+        cipher_suites: &[String],
+        ktls: bool,
+    ) -> Result<Self> {
+        let (certificates, key) = match (certificate, private_key) {
+            (Some(certificate), Some(private_key)) => {
+                let mut reader = BufReader::new(std::fs::File::open(private_key)?);
+                let key = rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+                    invalid_input(format!("no private key in \"{}\"", private_key))
+                })?;
+                (read_certificates(certificate)?, key)
+            }
+            (None, None) => {
+                let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+                    .map_err(|e| {
+                    invalid_input(format!("cannot generate certificate: {}", e))
+                })?;
+                let key = PrivatePkcs8KeyDer::from(generated.key_pair.serialize_der());
+                (
+                    vec![generated.cert.der().clone()],
+                    PrivateKeyDer::Pkcs8(key),
+                )
+            }
+            _ => {
+                return Err(invalid_input(
+                    "certificate and private_key must be given together".to_string(),
+                ))
+            }
+        };
+
+        let mut config = rustls::ServerConfig::builder_with_provider(provider(cipher_suites)?)
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .map_err(tls_error)?;
+        config.enable_secret_extraction = ktls;
+        if ktls {
+            // The kernel cannot receive session tickets sent after the handshake
+            config.send_tls13_tickets = 0;
+        }
+
+        Ok(Self {
+            tcp: TcpServer::new(address),
+            config: Arc::new(config),
+            ktls,
+        })
+    }
+}
+
+impl Server<TlsListener, TlsConnection> for TlsServer {
+    fn listen(&self) -> Result<TlsListener> {
+        Ok(TlsListener {
+            tcp: self.tcp.listen()?,
+            config: self.config.clone(),
+            ktls: self.ktls,
+        })
+    }
+}
@@ -1,5 +1,5 @@
 use crate::c::*;
-use crate::transport::{Client, Connection, Server, SetReadTimeout};
+use crate::transport::{Client, Connection, Server, SetReadTimeout, MAX_DATAGRAM_PACKET_SIZE};
 use crate::transports::sockets::{DgramListener, DgramSocket};
 use libc::*;
 use std::io::{Read, Write};
@@ -62,6 +62,18 @@ impl Connection for UdpConnection {
     fn header_size() -> usize {
         0
     }
+
+    fn max_packet_size() -> usize {
+        MAX_DATAGRAM_PACKET_SIZE
+    }
+
+    fn transport(&self) -> &'static str {
+        "udp"
+    }
+
+    fn peer_address(&self) -> Result<SocketAddrV4> {
+        Ok(self.destination)
+    }
 }
 
 impl Drop for UdpConnection {
@@ -123,7 +135,7 @@ impl Client<UdpConnection> for UdpClient {
             let fd = Fd::new(handle_os_result(socket(AF_INET, SOCK_DGRAM, 0))?);
             let socket = DgramSocket::new(fd);
             socket.connect(&self.address)?;
-            Ok(UdpConnection::new(socket, self.address.clone()))
+            Ok(UdpConnection::new(socket, self.address))
         }
     }
 }
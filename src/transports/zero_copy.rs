@@ -1,18 +1,26 @@
 use crate::transport::{Client, Connection, Listener, Server, SetReadTimeout};
-use libc::*;
 use std::io::{Read, Write};
+use std::net::SocketAddrV4;
+
+/// Zero-copy is not implemented yet, so every operation fails with this
+fn unsupported() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "zero-copy transport is not implemented",
+    )
+}
 
 struct ZeroCopyConnection {}
 
 impl Read for ZeroCopyConnection {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        todo!()
+    fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        Err(unsupported())
     }
 }
 
 impl Write for ZeroCopyConnection {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        todo!()
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(unsupported())
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
@@ -22,13 +30,13 @@ impl Write for ZeroCopyConnection {
 
 impl Clone for ZeroCopyConnection {
     fn clone(&self) -> Self {
-        todo!()
+        ZeroCopyConnection {}
     }
 }
 
 impl SetReadTimeout for ZeroCopyConnection {
-    fn set_read_timeout(&mut self, milliseconds: Option<u64>) -> std::io::Result<()> {
-        todo!()
+    fn set_read_timeout(&mut self, _milliseconds: Option<u64>) -> std::io::Result<()> {
+        Err(unsupported())
     }
 }
 
@@ -36,13 +44,29 @@ impl Connection for ZeroCopyConnection {
     fn header_size() -> usize {
         0
     }
+
+    fn transport(&self) -> &'static str {
+        "zero-copy"
+    }
+
+    fn peer_address(&self) -> crate::transport::Result<SocketAddrV4> {
+        Err(unsupported())
+    }
 }
 
 struct ZeroCopyListener {}
 
 impl Listener<ZeroCopyConnection> for ZeroCopyListener {
-    fn accept(&self) -> crate::transport::Result<ZeroCopyConnection> {
-        todo!()
+    fn accept(&self) -> crate::transport::Result<(ZeroCopyConnection, SocketAddrV4)> {
+        Err(unsupported())
+    }
+
+    fn local_address(&self) -> crate::transport::Result<SocketAddrV4> {
+        Err(unsupported())
+    }
+
+    fn set_accept_timeout(&self, _milliseconds: Option<u64>) -> crate::transport::Result<()> {
+        Err(unsupported())
     }
 }
 
@@ -50,7 +74,7 @@ struct ZeroCopyServer {}
 
 impl Server<ZeroCopyListener, ZeroCopyConnection> for ZeroCopyServer {
     fn listen(&self) -> crate::transport::Result<ZeroCopyListener> {
-        todo!()
+        Err(unsupported())
     }
 }
 
@@ -58,6 +82,6 @@ struct ZeroCopyClient {}
 
 impl Client<ZeroCopyConnection> for ZeroCopyClient {
     fn connect(&self) -> crate::transport::Result<ZeroCopyConnection> {
-        todo!()
+        Err(unsupported())
     }
 }
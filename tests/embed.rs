@@ -0,0 +1,100 @@
+use perf::program::{self, ClientConfig, Config, ServerConfig, TcpClientConfig, TcpServerConfig};
+use perf::test::{TestEvent, TestOptions, TestPlan};
+use perf::transport::TransportMode;
+use perf::AccessRules;
+use std::net::SocketAddrV4;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn server_config() -> Config {
+    Config {
+        transport: "tcp-server".to_string(),
+        tcp_server: Some(TcpServerConfig {
+            address: "127.0.0.1:0".parse().unwrap(),
+        }),
+        ..Default::default()
+    }
+}
+
+fn client_config(address: SocketAddrV4, duration: f64) -> Config {
+    let mut plan = TestPlan::new(1000);
+    plan.duration = duration;
+    Config {
+        transport: "tcp-client".to_string(),
+        tcp_client: Some(TcpClientConfig { address }),
+        client: Some(ClientConfig::new(TransportMode::Send, plan)),
+        ..Default::default()
+    }
+}
+
+/// Options which send every event through the returned channel, with reports only at the end
+fn listened_options() -> (TestOptions, Receiver<TestEvent>) {
+    let (sender, events) = channel();
+    let mut test_options = TestOptions::new(0.0);
+    test_options.add_listener(Box::new(sender));
+    (test_options, events)
+}
+
+#[test]
+fn client_runs_against_spawned_server() {
+    let server = program::spawn_server(server_config(), TestOptions::new(0.0)).unwrap();
+    assert_ne!(server.address().port(), 0);
+
+    let (test_options, events) = listened_options();
+    program::run(client_config(server.address(), 0.5), test_options).unwrap();
+    server.stop().unwrap();
+
+    let (data, summary) = events
+        .try_iter()
+        .find_map(|event| match event {
+            TestEvent::Finish(data, summary) => Some((data, summary)),
+            _ => None,
+        })
+        .expect("no finish event");
+    assert!(data.total_transfer > 0);
+    assert_eq!(summary.error, None);
+}
+
+#[test]
+fn stop_ends_the_running_test() {
+    let (server_options, server_events) = listened_options();
+    let server = program::spawn_server(server_config(), server_options).unwrap();
+    let address = server.address();
+    let client =
+        thread::spawn(move || program::run(client_config(address, 60.0), TestOptions::new(0.0)));
+
+    assert!(matches!(
+        server_events.recv_timeout(Duration::from_secs(5)),
+        Ok(TestEvent::Start(_))
+    ));
+    let stopping = Instant::now();
+    server.stop().unwrap();
+    assert!(stopping.elapsed() < Duration::from_secs(5));
+    client.join().unwrap().unwrap();
+
+    assert!(server_events
+        .try_iter()
+        .any(|event| matches!(event, TestEvent::Finish(data, _) if data.total_transfer > 0)));
+}
+
+#[test]
+fn denied_client_is_told() {
+    let mut config = server_config();
+    config.server = Some(ServerConfig {
+        access: AccessRules {
+            allow: Vec::new(),
+            deny: vec!["127.0.0.0/8".parse().unwrap()],
+        },
+        ..Default::default()
+    });
+    let server = program::spawn_server(config, TestOptions::new(0.0)).unwrap();
+
+    let result = program::run(client_config(server.address(), 0.5), TestOptions::new(0.0));
+    server.stop().unwrap();
+
+    match result {
+        Err(program::Error::Rejected { reason, .. }) => assert!(reason.contains("not allowed")),
+        result => panic!("expected a rejection, got {:?}", result.err()),
+    }
+}